@@ -18,6 +18,9 @@
 
             pkg-config
 
+            # dbus-daemon for tests/dbus.rs
+            dbus
+
             # WINIT_UNIX_BACKEND=x11
             /*xorg.libXcursor
             xorg.libXrandr
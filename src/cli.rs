@@ -0,0 +1,451 @@
+//! Subcommands controlling the running daemon over D-Bus.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+use clap::{Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::{eyre, WrapErr};
+use futures_util::StreamExt;
+use serde_json::json;
+use zbus::fdo::{DBusProxy, PropertiesProxy};
+use zbus::{CacheProperties, Connection};
+
+use uwu_notify::config::{self, Config};
+use uwu_notify::{headless, Entry, Markup, Urgency};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Print machine-readable JSON.
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Start the daemon even if another notification daemon is running, taking
+    /// over from it. Not allowed with a subcommand.
+    #[arg(long)]
+    pub replace: bool,
+    /// Without a subcommand, the daemon is started.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Remote(Remote),
+    /// Draw notifications into a PNG file the way the daemon would show them,
+    /// without talking to it.
+    Render {
+        /// A JSON list of notifications, each with `app_name`, `app_icon`,
+        /// `summary`, `body`, `actions` and `hints` like the arguments of `Notify`.
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+        /// The configuration to draw with, instead of the usual one.
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+}
+
+// the subcommands that talk to the running daemon, a plain comment as clap
+// would take a doc comment for the description of the whole program
+#[derive(Subcommand)]
+pub enum Remote {
+    /// Dismiss a notification, or all of them.
+    Dismiss {
+        #[arg(required_unless_present = "all")]
+        id: Option<u32>,
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+    /// Show a notification from the history again, by default the newest
+    /// one that is not shown anymore.
+    Restore { id: Option<u32> },
+    /// List past notifications, newest first.
+    History {
+        /// Only list notifications whose app name, summary or body contain this.
+        #[arg(long)]
+        search: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+        /// Forget every notification instead.
+        #[arg(long, conflicts_with_all = ["search", "limit"])]
+        clear: bool,
+    },
+    /// Show, or switch, Do Not Disturb.
+    Dnd { state: Option<DndState> },
+    /// Invoke an action of a notification.
+    Invoke { id: u32, action: String },
+    /// Reload the configuration file.
+    Reload,
+    /// Count the live notifications.
+    Count,
+    /// Print a line of JSON for a status bar whenever the count, Do Not
+    /// Disturb or the latest notification changes.
+    Watch {
+        #[arg(long, value_enum, default_value_t = Format::Waybar)]
+        format: Format,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DndState {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// For a `custom` module with `"return-type": "json"`.
+    Waybar,
+    /// The i3bar protocol, to be used as `status_command` of i3bar or swaybar.
+    I3bar,
+    /// A line of JSON per update, for a persistent i3blocks block with
+    /// `format=json`.
+    I3blocks,
+}
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[dbus_proxy(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "rs.uwu.Notify",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/rs/uwu/Notify"
+)]
+trait Control {
+    fn dismiss(&self, id: u32) -> zbus::Result<()>;
+    fn dismiss_all(&self) -> zbus::Result<u32>;
+    fn restore(&self, id: u32) -> zbus::Result<u32>;
+    fn invoke(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+    fn reload(&self) -> zbus::Result<()>;
+    fn count(&self) -> zbus::Result<(u32, u32)>;
+    fn notifications(&self) -> zbus::Result<Vec<Entry>>;
+    fn toggle_do_not_disturb(&self) -> zbus::Result<bool>;
+    #[dbus_proxy(property)]
+    fn do_not_disturb(&self) -> zbus::Result<bool>;
+    #[dbus_proxy(property)]
+    fn set_do_not_disturb(&self, enabled: bool) -> zbus::Result<()>;
+    #[dbus_proxy(signal)]
+    fn notification_added(&self, id: u32) -> zbus::Result<()>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "rs.uwu.Notify.History",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/rs/uwu/Notify"
+)]
+trait History {
+    fn list(&self, offset: u32, limit: u32) -> zbus::Result<Vec<Entry>>;
+    fn search(&self, query: &str) -> zbus::Result<Vec<Entry>>;
+    fn clear(&self) -> zbus::Result<()>;
+}
+
+/// Run a subcommand against the daemon on the session bus.
+pub async fn run(command: Remote, json: bool) -> color_eyre::Result<()> {
+    let conn = Connection::session()
+        .await
+        .wrap_err("failed to connect to the session bus")?;
+    let control = ControlProxy::new(&conn).await?;
+    let history = HistoryProxy::new(&conn).await?;
+
+    let output = match command {
+        Remote::Dismiss { all: true, .. } => {
+            let count = control.dismiss_all().await.wrap_err(NOT_RUNNING)?;
+            Output::new(json!({ "dismissed": count }), format!("dismissed {count}"))
+        }
+        Remote::Dismiss { id, .. } => {
+            let id = id.expect("clap requires an id without --all");
+            control.dismiss(id).await.wrap_err(NOT_RUNNING)?;
+            Output::new(json!({ "dismissed": 1 }), format!("dismissed {id}"))
+        }
+        Remote::Restore { id } => {
+            let id = control
+                .restore(id.unwrap_or(0))
+                .await
+                .wrap_err(NOT_RUNNING)?;
+            Output::new(json!({ "id": id }), format!("restored as {id}"))
+        }
+        Remote::History { clear: true, .. } => {
+            history.clear().await.wrap_err(NOT_RUNNING)?;
+            Output::new(json!({ "cleared": true }), "cleared the history".to_owned())
+        }
+        Remote::History { search, limit, .. } => {
+            let entries = match search {
+                Some(query) => {
+                    let mut entries = history.search(&query).await.wrap_err(NOT_RUNNING)?;
+                    entries.truncate(limit as usize);
+                    entries
+                }
+                None => history.list(0, limit).await.wrap_err(NOT_RUNNING)?,
+            };
+            let text = entries.iter().map(describe).collect::<Vec<_>>().join("\n");
+            Output::new(json!(entries), text)
+        }
+        Remote::Dnd { state } => {
+            let enabled = match state {
+                None => control.do_not_disturb().await.wrap_err(NOT_RUNNING)?,
+                Some(DndState::Toggle) => control
+                    .toggle_do_not_disturb()
+                    .await
+                    .wrap_err(NOT_RUNNING)?,
+                Some(state) => {
+                    let enabled = matches!(state, DndState::On);
+                    control
+                        .set_do_not_disturb(enabled)
+                        .await
+                        .wrap_err(NOT_RUNNING)?;
+                    enabled
+                }
+            };
+            let text = if enabled { "on" } else { "off" };
+            Output::new(json!({ "dnd": enabled }), text.to_owned())
+        }
+        Remote::Invoke { id, action } => {
+            control.invoke(id, &action).await.wrap_err(NOT_RUNNING)?;
+            Output::new(
+                json!({ "id": id, "action": action }),
+                format!("invoked {action:?} on {id}"),
+            )
+        }
+        Remote::Reload => {
+            control
+                .reload()
+                .await
+                .wrap_err("failed to reload the config")?;
+            Output::new(json!({ "reloaded": true }), "reloaded".to_owned())
+        }
+        Remote::Count => {
+            let (count, suppressed) = control.count().await.wrap_err(NOT_RUNNING)?;
+            Output::new(
+                json!({ "count": count, "suppressed": suppressed }),
+                count.to_string(),
+            )
+        }
+        Remote::Watch { format } => return watch(&conn, format).await,
+    };
+    output.print(json);
+    Ok(())
+}
+
+/// Draw the notifications described in `input` into `out`, without the daemon.
+pub fn render(
+    input: &Path,
+    out: &Path,
+    config: Option<PathBuf>,
+    json: bool,
+) -> color_eyre::Result<()> {
+    let config = match config.or_else(config::path) {
+        Some(path) => Config::load(&path).map_err(|err| eyre!(err))?,
+        None => Config::default(),
+    };
+    let notifications = fs::read_to_string(input)
+        .wrap_err_with(|| format!("failed to read {}", input.display()))?;
+    let notifications =
+        serde_json::from_str(&notifications).wrap_err_with(|| format!("in {}", input.display()))?;
+    let canvas = headless::render(notifications, &config)?;
+    let file =
+        fs::File::create(out).wrap_err_with(|| format!("failed to create {}", out.display()))?;
+    canvas
+        .write_png(io::BufWriter::new(file))
+        .wrap_err_with(|| format!("failed to write {}", out.display()))?;
+    Output::new(json!({ "out": out }), String::new()).print(json);
+    Ok(())
+}
+
+/// Print the status whenever it changes, until the session bus goes away.
+///
+/// The daemon may come and go in the meantime, while it is away the status
+/// says so instead of failing, so that the bar does not have to restart us.
+async fn watch(conn: &Connection, format: Format) -> color_eyre::Result<()> {
+    // a cache would be stuck empty if the daemon is not running yet
+    let control = ControlProxy::builder(conn)
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let properties = PropertiesProxy::builder(conn)
+        .destination("org.freedesktop.Notifications")?
+        .path("/rs/uwu/Notify")?
+        .build()
+        .await?;
+    let notifications = NotificationsProxy::new(conn).await?;
+    let mut closed = notifications.receive_notification_closed().await?;
+    let mut added = control.receive_notification_added().await?;
+    let mut dnd = properties.receive_properties_changed().await?;
+    let bus = DBusProxy::new(conn).await?;
+    let mut owner = bus
+        .receive_name_owner_changed_with_args(&[(0, "org.freedesktop.Notifications")])
+        .await?;
+
+    if let Format::I3bar = format {
+        // the header, followed by an endless array of status lines
+        println!("{}", json!({ "version": 1 }));
+        println!("[");
+    }
+    let mut last = None;
+    loop {
+        let line = Status::query(&control).await.render(format).to_string();
+        if last.as_ref() != Some(&line) {
+            match format {
+                Format::I3bar if last.is_some() => println!(",[{line}]"),
+                Format::I3bar => println!("[{line}]"),
+                _ => println!("{line}"),
+            }
+            last = Some(line);
+        }
+        tokio::select! {
+            Some(_) = closed.next() => {}
+            Some(_) = added.next() => {}
+            Some(_) = dnd.next() => {}
+            Some(_) = owner.next() => {}
+            else => return Ok(()),
+        }
+    }
+}
+
+/// What a status bar shows.
+struct Status {
+    running: bool,
+    count: u32,
+    dnd: bool,
+    latest: Option<Entry>,
+}
+
+impl Status {
+    async fn query(control: &ControlProxy<'_>) -> Self {
+        let status = async {
+            let (count, _) = control.count().await?;
+            let dnd = control.do_not_disturb().await?;
+            let latest = control.notifications().await?.into_iter().next();
+            zbus::Result::Ok(Self {
+                running: true,
+                count,
+                dnd,
+                latest,
+            })
+        };
+        status.await.unwrap_or_else(|err| {
+            tracing::debug!("failed to query the daemon: {err}");
+            Self {
+                running: false,
+                count: 0,
+                dnd: false,
+                latest: None,
+            }
+        })
+    }
+
+    /// `none`, `notification`, `dnd-none`, `dnd-notification` or `offline`.
+    fn class(&self) -> &'static str {
+        match (self.running, self.dnd, self.count > 0) {
+            (false, _, _) => "offline",
+            (true, false, false) => "none",
+            (true, false, true) => "notification",
+            (true, true, false) => "dnd-none",
+            (true, true, true) => "dnd-notification",
+        }
+    }
+
+    fn latest(&self) -> Option<String> {
+        let latest = self.latest.as_ref()?;
+        Some(match latest.app_name.as_str() {
+            "" => latest.summary.clone(),
+            app_name => format!("{app_name}: {}", latest.summary),
+        })
+    }
+
+    fn render(&self, format: Format) -> serde_json::Value {
+        let count = if self.running {
+            self.count.to_string()
+        } else {
+            String::new()
+        };
+        match format {
+            Format::Waybar => {
+                let tooltip = match self.latest() {
+                    _ if !self.running => "uwu-notify is not running".to_owned(),
+                    Some(latest) => latest,
+                    None => "No notifications".to_owned(),
+                };
+                json!({
+                    "text": count,
+                    "alt": self.class(),
+                    "tooltip": tooltip,
+                    "class": self.class(),
+                })
+            }
+            Format::I3bar | Format::I3blocks => {
+                let mut full_text = count.clone();
+                if self.dnd {
+                    full_text = format!("DND {full_text}");
+                }
+                if let Some(latest) = self.latest() {
+                    full_text = format!("{full_text} {latest}");
+                }
+                let critical = self
+                    .latest
+                    .as_ref()
+                    .is_some_and(|latest| latest.urgency == Urgency::Critical as u8);
+                json!({
+                    "name": "uwu-notify",
+                    "instance": self.class(),
+                    "full_text": full_text,
+                    "short_text": count,
+                    "urgent": critical,
+                })
+            }
+        }
+    }
+}
+
+const NOT_RUNNING: &str = "failed to talk to uwu-notify, is it running?";
+
+/// What a subcommand prints, as JSON for scripts or text for people.
+struct Output {
+    json: serde_json::Value,
+    text: String,
+}
+
+impl Output {
+    fn new(json: serde_json::Value, text: String) -> Self {
+        Self { json, text }
+    }
+
+    fn print(self, json: bool) {
+        if json {
+            println!("{}", self.json);
+        } else if !self.text.is_empty() {
+            println!("{}", self.text);
+        }
+    }
+}
+
+/// A line describing a history entry.
+fn describe(entry: &Entry) -> String {
+    let time = Local
+        .timestamp_opt(entry.timestamp as i64, 0)
+        .single()
+        .map_or_else(String::new, |time| {
+            time.format("%Y-%m-%d %H:%M").to_string()
+        });
+    let mut line = format!(
+        "{:>5}  {time}  {}: {}",
+        entry.id, entry.app_name, entry.summary
+    );
+    let body = Markup::parse(&entry.body).text();
+    if let Some(first) = body.lines().find(|line| !line.trim().is_empty()) {
+        line.push_str(" - ");
+        line.push_str(first.trim());
+    }
+    line
+}
@@ -0,0 +1,450 @@
+//! The configuration file, `$XDG_CONFIG_HOME/uwu-notify/config.toml`.
+//!
+//! Every setting is optional, an empty or missing file gives the defaults.
+//! Values are checked while parsing, so that mistakes are reported with the
+//! line and column they were made at.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use egui::Color32;
+use serde::Deserialize;
+
+use crate::rules::Rule;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Only read at startup.
+    pub renderer: Renderer,
+    pub layer: Layer,
+    pub anchor: Anchor,
+    pub margin: Margin,
+    /// Width of the notification stack in logical pixels.
+    pub width: NonZeroU32,
+    /// Cards that do not fit are cut off.
+    pub max_height: Option<NonZeroU32>,
+    /// How many notifications are shown at once, newest first.
+    pub max_visible: Option<NonZeroUsize>,
+    pub timeouts: Timeouts,
+    pub font: Font,
+    pub colors: Colors,
+    pub history: History,
+    pub dnd: Dnd,
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            renderer: Renderer::default(),
+            layer: Layer::default(),
+            anchor: Anchor::default(),
+            margin: Margin::default(),
+            width: NonZeroU32::new(300).unwrap(),
+            max_height: None,
+            max_visible: None,
+            timeouts: Timeouts::default(),
+            font: Font::default(),
+            colors: Colors::default(),
+            history: History::default(),
+            dnd: Dnd::default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// How the notifications are drawn.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Renderer {
+    /// OpenGL, or the CPU if setting up EGL fails.
+    #[default]
+    Auto,
+    Gl,
+    Software,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer {
+    Background,
+    Bottom,
+    #[default]
+    Top,
+    Overlay,
+}
+
+/// The corner or edge of the output the notifications are stacked at.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    #[default]
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Space between the notifications and the edges of the output, in logical pixels.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Margin {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// Default timeouts by urgency in seconds, `0` meaning never.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    pub low: Seconds,
+    pub normal: Seconds,
+    pub critical: Seconds,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        let defaults = crate::store::Timeouts::default();
+        Self {
+            low: Seconds(defaults.low),
+            normal: Seconds(defaults.normal),
+            critical: Seconds(defaults.critical),
+        }
+    }
+}
+
+impl From<Timeouts> for crate::store::Timeouts {
+    fn from(timeouts: Timeouts) -> Self {
+        Self {
+            low: timeouts.low.0,
+            normal: timeouts.normal.0,
+            critical: timeouts.critical.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Font {
+    /// A TrueType or OpenType font to use instead of the built-in one.
+    pub path: Option<PathBuf>,
+    /// Size of body text in points.
+    pub size: Option<Size>,
+}
+
+/// Colors as `#rrggbb` or `#rrggbbaa`. Unset colors follow the egui theme.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    /// Behind the cards, transparent by default.
+    pub surface: Option<Color>,
+    pub background: Option<Color>,
+    pub text: Option<Color>,
+    pub border: Option<Color>,
+    /// Borders and summaries of critical notifications.
+    pub critical: Option<Color>,
+    pub link: Option<Color>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct History {
+    /// `0` keeps no history at all.
+    pub max_entries: usize,
+    /// `0` keeps entries forever.
+    pub max_age_days: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        let defaults = crate::history::Retention::default();
+        Self {
+            max_entries: defaults.max_entries.unwrap_or(0),
+            max_age_days: defaults
+                .max_age
+                .map_or(0, |age| age.as_secs() / SECONDS_PER_DAY),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+impl From<History> for crate::history::Retention {
+    fn from(history: History) -> Self {
+        Self {
+            max_entries: Some(history.max_entries),
+            max_age: (history.max_age_days > 0)
+                .then(|| Duration::from_secs(history.max_age_days * SECONDS_PER_DAY)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Dnd {
+    pub allow_critical: bool,
+    /// Applications, by name or desktop entry, whose notifications still pop up.
+    pub exceptions: Vec<String>,
+    pub schedule: Option<Schedule>,
+}
+
+impl Default for Dnd {
+    fn default() -> Self {
+        let defaults = crate::dnd::Dnd::default();
+        Self {
+            allow_critical: defaults.allow_critical,
+            exceptions: defaults.exceptions,
+            schedule: None,
+        }
+    }
+}
+
+/// Times of day as `HH:MM`, see [`crate::dnd::Schedule`].
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub start: TimeOfDay,
+    pub end: TimeOfDay,
+}
+
+impl From<Schedule> for crate::dnd::Schedule {
+    fn from(schedule: Schedule) -> Self {
+        Self {
+            start: schedule.start.0,
+            end: schedule.end.0,
+        }
+    }
+}
+
+/// A duration in seconds, `0` meaning none.
+#[derive(Debug, Clone, Copy)]
+pub struct Seconds(pub Option<Duration>);
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        let duration = Duration::try_from_secs_f64(secs).map_err(|_| {
+            serde::de::Error::custom(format!("expected a positive number of seconds, got {secs}"))
+        })?;
+        Ok(Self(Some(duration).filter(|d| !d.is_zero())))
+    }
+}
+
+/// A font size in points.
+#[derive(Debug, Clone, Copy)]
+pub struct Size(pub f32);
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let size = f32::deserialize(deserializer)?;
+        if !(1.0..=200.0).contains(&size) {
+            let msg = format!("expected a font size between 1 and 200, got {size}");
+            return Err(serde::de::Error::custom(msg));
+        }
+        Ok(Self(size))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Color(pub Color32);
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        parse_color(&color).map(Self).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "expected a color like \"#rrggbb\" or \"#rrggbbaa\", got {color:?}"
+            ))
+        })
+    }
+}
+
+fn parse_color(color: &str) -> Option<Color32> {
+    let hex = color.strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some(Color32::from_rgba_unmultiplied(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        alpha,
+    ))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeOfDay(pub NaiveTime);
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&time, "%H:%M")
+            .map(Self)
+            .map_err(|_| {
+                serde::de::Error::custom(format!("expected a time like \"22:30\", got {time:?}"))
+            })
+    }
+}
+
+impl Config {
+    /// Read the configuration file at `path`, or the defaults if there is none.
+    ///
+    /// The error describes what is wrong and where, ready to be shown to the user.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!("no config file at {}, using defaults", path.display());
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(format!("failed to read {}: {err}", path.display())),
+        };
+        toml::from_str(&contents).map_err(|err| format!("in {}: {err}", path.display()))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/uwu-notify/config.toml`, or `~/.config/uwu-notify/config.toml`.
+pub fn path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("uwu-notify").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load `contents` as the config file of the test.
+    fn load(test: &str, contents: &str) -> Result<Config, String> {
+        let dir = env::temp_dir().join(format!("uwu-notify-{test}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, contents).unwrap();
+        let config = Config::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        config
+    }
+
+    #[track_caller]
+    fn error(test: &str, contents: &str, at: &str, message: &str) {
+        let err = load(test, contents).unwrap_err();
+        assert!(err.contains(at), "{err}");
+        assert!(err.contains(message), "{err}");
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let config = Config::load(Path::new("/nonexistent/config.toml")).unwrap();
+        assert_eq!(config.width.get(), 300);
+        assert!(config.rules.is_empty());
+    }
+
+    #[test]
+    fn settings() {
+        let config = load(
+            "settings",
+            r##"
+width = 400
+anchor = "bottom-left"
+
+[colors]
+text = "#ff000080"
+link = "#00ff00"
+
+[timeouts]
+normal = 2.5
+critical = 0
+
+[dnd.schedule]
+start = "22:00"
+end = "07:30"
+"##,
+        )
+        .unwrap();
+        assert_eq!(config.width.get(), 400);
+        assert!(matches!(config.anchor, Anchor::BottomLeft));
+        assert_eq!(
+            config.colors.text.unwrap().0,
+            Color32::from_rgba_unmultiplied(255, 0, 0, 128)
+        );
+        assert_eq!(config.colors.link.unwrap().0, Color32::from_rgb(0, 255, 0));
+        assert_eq!(config.timeouts.normal.0, Some(Duration::from_millis(2500)));
+        assert_eq!(config.timeouts.critical.0, None);
+        let schedule = crate::dnd::Schedule::from(config.dnd.schedule.unwrap());
+        assert_eq!(schedule.end, NaiveTime::from_hms_opt(7, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        error(
+            "unknown-fields",
+            "width = 300\nwidht = 3\n",
+            "line 2, column 1",
+            "unknown field `widht`",
+        );
+        error(
+            "unknown-nested-fields",
+            "[margin]\ntop = 1\nmiddle = 2\n",
+            "line 3, column 1",
+            "unknown field `middle`",
+        );
+    }
+
+    #[test]
+    fn bad_colors() {
+        for color in ["#12345", "123456", "#gggggg", "#1234567", "#ééé"] {
+            error(
+                "bad-colors",
+                &format!("[colors]\ntext = {color:?}\n"),
+                "line 2, column 8",
+                &format!("expected a color like \"#rrggbb\" or \"#rrggbbaa\", got {color:?}"),
+            );
+        }
+    }
+
+    #[test]
+    fn bad_values() {
+        error(
+            "bad-font-size",
+            "[font]\nsize = 0\n",
+            "line 2, column 8",
+            "expected a font size between 1 and 200, got 0",
+        );
+        error(
+            "bad-time",
+            "[dnd.schedule]\nstart = \"25:00\"\nend = \"07:00\"\n",
+            "line 2, column 9",
+            "expected a time like \"22:30\", got \"25:00\"",
+        );
+        error(
+            "bad-timeout",
+            "[timeouts]\nlow = -1\n",
+            "line 2, column 7",
+            "expected a positive number of seconds, got -1",
+        );
+        error(
+            "bad-anchor",
+            "anchor = \"up\"\n",
+            "line 1, column 10",
+            "unknown variant `up`",
+        );
+    }
+}
@@ -0,0 +1,821 @@
+//! The D-Bus side of the daemon, serving `org.freedesktop.Notifications` and
+//! uwu-notify's own interfaces next to it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use chrono::Local;
+use color_eyre::eyre::bail;
+use futures_util::future::OptionFuture;
+use futures_util::StreamExt;
+use inotify::{Inotify, WatchMask};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use zbus::fdo::{DBusProxy, NameLostStream, RequestNameFlags};
+use zbus::names::BusName;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{Connection, ConnectionBuilder, SignalContext};
+
+use crate::config::{self, Config};
+use crate::hints::Hints;
+use crate::history::{self, Entry, History, SharedHistory};
+use crate::notification::{CloseReason, Message, Signal};
+use crate::rules;
+use crate::store::{NewNotification, Notifications, SharedStore, Store};
+use crate::wayland;
+
+/// The bus name notification daemons serve on.
+const NAME: &str = "org.freedesktop.Notifications";
+
+/// How notifications are shown.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Frontend {
+    /// On a layer surface of the Wayland compositor.
+    #[default]
+    Wayland,
+    /// Not at all, for tests. Notifications still expire, and can be
+    /// dismissed or have their actions invoked through `rs.uwu.Notify`.
+    Null,
+}
+
+/// What the daemon serves on and where it keeps its files.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The address of the bus to serve on, the session bus if `None`.
+    pub address: Option<String>,
+    /// The configuration file, reloaded when it changes. Without one the
+    /// defaults are used.
+    pub config: Option<PathBuf>,
+    /// Where the history is kept. Without a file it is forgotten on exit.
+    pub history: Option<PathBuf>,
+    /// Take the bus name over from another notification daemon, if it lets
+    /// us. Either way, we let the next one take it from us.
+    pub replace: bool,
+    pub frontend: Frontend,
+}
+
+impl Default for Options {
+    /// The session bus and the usual XDG directories.
+    fn default() -> Self {
+        let config = config::path();
+        if config.is_none() {
+            tracing::warn!("neither XDG_CONFIG_HOME nor HOME is set, using the default config");
+        }
+        let history = history::path();
+        if history.is_none() {
+            tracing::warn!("neither XDG_STATE_HOME nor HOME is set, history will not be kept");
+        }
+        Self {
+            address: None,
+            config,
+            history,
+            replace: false,
+            frontend: Frontend::Wayland,
+        }
+    }
+}
+
+struct NotificationServer {
+    store: SharedStore,
+    history: SharedHistory,
+    tx: UnboundedSender<Message>,
+    signals: UnboundedSender<Signal>,
+}
+
+#[zbus::dbus_interface(name = "org.freedesktop.Notifications")]
+impl NotificationServer {
+    /// CloseNotification method
+    async fn close_notification(
+        &self,
+        #[zbus(signal_context)] ctx: SignalContext<'_>,
+        id: u32,
+    ) -> zbus::fdo::Result<()> {
+        // The spec asks for an empty error reply when the notification is
+        // already gone, but clients routinely close notifications that have
+        // expired in the meantime, so like other servers we just ignore it.
+        if self.store.lock().unwrap().remove(id).is_none() {
+            tracing::debug!(id, "asked to close unknown notification");
+            return Ok(());
+        }
+        if self.tx.send(Message::Redraw).is_err() {
+            tracing::error!("wayland event loop is gone, notification will stay shown");
+        }
+
+        Self::notification_closed(&ctx, id, CloseReason::Closed as u32).await?;
+        Ok(())
+    }
+
+    /// GetCapabilities method
+    fn get_capabilities(&self) -> &'static [&'static str] {
+        &[
+            "actions",
+            "action-icons",
+            "body",
+            "body-markup",
+            "body-hyperlinks",
+            "inline-reply",
+        ]
+    }
+
+    /// GetServerInformation method
+    fn get_server_information(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        (
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_AUTHORS"),
+            env!("CARGO_PKG_VERSION"),
+            "1.2",
+        )
+    }
+
+    /// Notify method
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self))]
+    fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let mut store = self.store.lock().unwrap();
+        let rules = store
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches_hints(&hints))
+            .map(|(index, rule)| (index, rule.clone()))
+            .collect::<Vec<_>>();
+        let mut new = NewNotification {
+            app_name,
+            app_icon,
+            summary,
+            body,
+            actions,
+            hints: Hints::parse(hints),
+            expire_timeout,
+            style: Default::default(),
+            hidden: false,
+            skip_history: false,
+        };
+        let mut hooks = Vec::new();
+        for (index, rule) in rules {
+            if rule.matches(&new) {
+                tracing::debug!(rule = rule.describe(index), "rule matched");
+                rule.apply(&mut new);
+                hooks.extend(rule.exec);
+            }
+        }
+
+        let id = store.insert(replaces_id, new);
+        // writing the history and forking happen once the store is let go of,
+        // so that they hold up neither other calls nor drawing
+        let (entry, commands) = match store.get(id) {
+            Some(notification) => (
+                (!notification.skip_history && !notification.hints.transient)
+                    .then(|| Entry::new(notification)),
+                hooks
+                    .iter()
+                    .map(|hook| hook.command(notification))
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+        // never shown, so it is closed as soon as the rules are done with it,
+        // rather than pretending that it expired
+        let hidden = store.get(id).is_some_and(|n| n.hidden);
+        if hidden {
+            store.remove(id);
+        }
+        drop(store);
+        if let Some(entry) = entry {
+            self.history.lock().unwrap().record(entry);
+        }
+        for command in commands {
+            rules::spawn(command);
+        }
+
+        if self.tx.send(Message::Notify(id)).is_err() {
+            tracing::error!("wayland event loop is gone, notification will not be shown");
+        }
+        if self.signals.send(Signal::NotificationAdded(id)).is_err() {
+            tracing::error!("signal emitter is gone, cannot emit signal");
+        }
+        if hidden {
+            tracing::debug!(id, "notification hidden by a rule");
+            let closed = Signal::NotificationClosed(id, CloseReason::Undefined);
+            if self.signals.send(closed).is_err() {
+                tracing::error!("signal emitter is gone, cannot emit signal");
+            }
+        }
+
+        id
+    }
+
+    /// ActionInvoked signal
+    #[dbus_interface(signal)]
+    async fn action_invoked(
+        ctx: &SignalContext<'_>,
+        id: u32,
+        action_key: String,
+    ) -> zbus::Result<()>;
+
+    /// ActivationToken signal
+    #[dbus_interface(signal)]
+    async fn activation_token(
+        ctx: &SignalContext<'_>,
+        id: u32,
+        activation_token: String,
+    ) -> zbus::Result<()>;
+
+    /// NotificationReplied signal
+    #[dbus_interface(signal)]
+    async fn notification_replied(
+        ctx: &SignalContext<'_>,
+        id: u32,
+        text: String,
+    ) -> zbus::Result<()>;
+
+    /// NotificationClosed signal
+    #[dbus_interface(signal)]
+    async fn notification_closed(ctx: &SignalContext<'_>, id: u32, reason: u32)
+        -> zbus::Result<()>;
+}
+
+/// Lets scripts look up notifications after they were closed.
+struct HistoryServer {
+    history: SharedHistory,
+}
+
+#[zbus::dbus_interface(name = "rs.uwu.Notify.History")]
+impl HistoryServer {
+    /// Up to `limit` notifications, newest first, skipping the `offset` newest.
+    fn list(&self, offset: u32, limit: u32) -> Vec<Entry> {
+        self.history
+            .lock()
+            .unwrap()
+            .list(offset as usize, limit as usize)
+    }
+
+    /// Notifications whose app name, summary or body contain `query`, newest first.
+    fn search(&self, query: &str) -> Vec<Entry> {
+        self.history.lock().unwrap().search(query)
+    }
+
+    /// The most recent notification with the given id.
+    fn get(&self, id: u32) -> zbus::fdo::Result<Entry> {
+        self.history
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("no notification with id {id} in history"))
+            })
+    }
+
+    /// Forget every notification.
+    fn clear(&self) {
+        self.history.lock().unwrap().clear();
+    }
+}
+
+/// Controls the daemon itself, for `uwu-notify`'s subcommands and status bars.
+struct ControlServer {
+    store: SharedStore,
+    history: SharedHistory,
+    tx: UnboundedSender<Message>,
+    signals: UnboundedSender<Signal>,
+    reloader: Reloader,
+}
+
+impl ControlServer {
+    fn signal(&self, signal: Signal) {
+        if self.signals.send(signal).is_err() {
+            tracing::error!("signal emitter is gone, cannot emit signal");
+        }
+    }
+
+    fn redraw(&self) {
+        if self.tx.send(Message::Redraw).is_err() {
+            tracing::error!("wayland event loop is gone, cannot redraw");
+        }
+    }
+}
+
+#[zbus::dbus_interface(name = "rs.uwu.Notify")]
+impl ControlServer {
+    /// Dismiss a notification as if the user clicked it away.
+    fn dismiss(&self, id: u32) -> zbus::fdo::Result<()> {
+        if self.store.lock().unwrap().remove(id).is_none() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "no notification with id {id}"
+            )));
+        }
+        self.redraw();
+        self.signal(Signal::NotificationClosed(id, CloseReason::Dismissed));
+        Ok(())
+    }
+
+    /// Dismiss every notification, returning how many there were.
+    fn dismiss_all(&self) -> u32 {
+        let ids = self.store.lock().unwrap().clear();
+        self.redraw();
+        for &id in &ids {
+            self.signal(Signal::NotificationClosed(id, CloseReason::Dismissed));
+        }
+        ids.len() as u32
+    }
+
+    /// Show a notification from the history again, returning its new id.
+    ///
+    /// With an id of 0, the newest notification that is not shown anymore is restored.
+    fn restore(&self, id: u32) -> zbus::fdo::Result<u32> {
+        let mut store = self.store.lock().unwrap();
+        let history = self.history.lock().unwrap();
+        let entry = if id == 0 {
+            history
+                .iter()
+                .find(|entry| !store.iter().any(|n| entry.describes(n)))
+        } else {
+            history.get(id)
+        };
+        let Some(entry) = entry else {
+            return Err(zbus::fdo::Error::InvalidArgs(match id {
+                0 => "no notification to restore".to_owned(),
+                id => format!("no notification with id {id} in history"),
+            }));
+        };
+
+        let mut hints = HashMap::new();
+        hints.insert("urgency".to_owned(), Value::U8(entry.urgency).into());
+        if !entry.category.is_empty() {
+            hints.insert("category".to_owned(), Value::from(&entry.category).into());
+        }
+        let new = NewNotification {
+            app_name: entry.app_name.clone(),
+            app_icon: entry.app_icon.clone(),
+            summary: entry.summary.clone(),
+            body: entry.body.clone(),
+            actions: Vec::new(),
+            hints: Hints::parse(hints),
+            expire_timeout: -1,
+            style: Default::default(),
+            hidden: false,
+            // it is in the history already
+            skip_history: true,
+        };
+        drop(history);
+        let id = store.insert(0, new);
+        drop(store);
+
+        if self.tx.send(Message::Notify(id)).is_err() {
+            tracing::error!("wayland event loop is gone, notification will not be shown");
+        }
+        self.signal(Signal::NotificationAdded(id));
+        Ok(id)
+    }
+
+    /// Invoke an action as if the user clicked it.
+    fn invoke(&self, id: u32, action_key: &str) -> zbus::fdo::Result<()> {
+        let mut store = self.store.lock().unwrap();
+        let Some(notification) = store.get(id) else {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "no notification with id {id}"
+            )));
+        };
+        if notification.action(action_key).is_none() {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "notification {id} has no action {action_key:?}"
+            )));
+        }
+        let resident = notification.hints.resident;
+        if !resident {
+            store.remove(id);
+        }
+        drop(store);
+        self.redraw();
+
+        self.signal(Signal::ActionInvoked(id, action_key.to_owned()));
+        if !resident {
+            self.signal(Signal::NotificationClosed(id, CloseReason::Dismissed));
+        }
+        Ok(())
+    }
+
+    /// Reload the configuration file.
+    fn reload(&self) -> zbus::fdo::Result<()> {
+        self.reloader.reload().map_err(zbus::fdo::Error::Failed)
+    }
+
+    /// The number of live notifications, and how many of them Do Not Disturb kept back.
+    fn count(&self) -> (u32, u32) {
+        let store = self.store.lock().unwrap();
+        // like `notifications`, leaving out those hidden by rules
+        let live = || store.iter().filter(|n| !n.hidden);
+        let count = live().count() as u32;
+        let suppressed = live().filter(|n| n.suppressed).count() as u32;
+        (count, suppressed)
+    }
+
+    /// The live notifications, newest first, leaving out those kept from
+    /// popping up by rules.
+    fn notifications(&self) -> Vec<Entry> {
+        let store = self.store.lock().unwrap();
+        let mut notifications = store.iter().filter(|n| !n.hidden).collect::<Vec<_>>();
+        notifications.sort_by_key(|n| std::cmp::Reverse(n.updated_at));
+        notifications.into_iter().map(Entry::new).collect()
+    }
+
+    /// The hints of a live notification as the daemon understood them.
+    fn hints(&self, id: u32) -> zbus::fdo::Result<HashMap<String, OwnedValue>> {
+        match self.store.lock().unwrap().get(id) {
+            Some(notification) => Ok(notification.hints.decoded()),
+            None => Err(zbus::fdo::Error::InvalidArgs(format!(
+                "no notification with id {id}"
+            ))),
+        }
+    }
+
+    /// Switch Do Not Disturb on or off, returning whether it is now on.
+    async fn toggle_do_not_disturb(
+        &self,
+        #[zbus(signal_context)] ctx: SignalContext<'_>,
+    ) -> zbus::fdo::Result<bool> {
+        let enabled = {
+            let mut store = self.store.lock().unwrap();
+            store.dnd.enabled = !store.dnd.enabled;
+            store.dnd.enabled
+        };
+        tracing::debug!(enabled, "toggled do not disturb");
+        self.redraw();
+        self.do_not_disturb_changed(&ctx).await?;
+        Ok(enabled)
+    }
+
+    /// Whether notifications are kept from popping up.
+    #[dbus_interface(property)]
+    fn do_not_disturb(&self) -> bool {
+        self.store.lock().unwrap().dnd.enabled
+    }
+
+    #[dbus_interface(property)]
+    fn set_do_not_disturb(&mut self, enabled: bool) {
+        tracing::debug!(enabled, "set do not disturb");
+        self.store.lock().unwrap().dnd.enabled = enabled;
+        self.redraw();
+    }
+
+    /// A notification was added or replaced, for status bars to follow
+    /// along with `NotificationClosed`.
+    #[dbus_interface(signal)]
+    async fn notification_added(ctx: &SignalContext<'_>, id: u32) -> zbus::Result<()>;
+}
+
+/// Switch Do Not Disturb on and off following its schedule.
+///
+/// Sleeps until the schedule window opens or closes, or the configuration is
+/// reloaded. The monotonic clock stops while the machine is suspended, so
+/// sleeps are capped to notice the edges soon enough after resuming.
+async fn follow_schedule(conn: Connection, reloaded: Arc<Notify>) -> zbus::Result<()> {
+    const MAX_SLEEP: Duration = Duration::from_secs(15 * 60);
+
+    let control = conn
+        .object_server()
+        .interface::<_, ControlServer>("/rs/uwu/Notify")
+        .await?;
+    loop {
+        let (changed, sleep) = {
+            let server = control.get().await;
+            let mut store = server.store.lock().unwrap();
+            let sleep = store
+                .dnd
+                .schedule
+                .map(|schedule| schedule.until_edge(Local::now().time()).min(MAX_SLEEP));
+            (store.dnd.tick(), sleep)
+        };
+        if changed {
+            tracing::debug!("do not disturb schedule switched");
+            let server = control.get().await;
+            server.redraw();
+            server
+                .do_not_disturb_changed(control.signal_context())
+                .await?;
+        }
+        match sleep {
+            Some(sleep) => tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                _ = reloaded.notified() => {}
+            },
+            None => reloaded.notified().await,
+        }
+    }
+}
+
+/// Apply the parts of the configuration the D-Bus side is concerned with.
+fn apply_config(config: &Config, store: &SharedStore, history: &SharedHistory) {
+    let mut store = store.lock().unwrap();
+    store.timeouts = config.timeouts.into();
+    store.dnd.allow_critical = config.dnd.allow_critical;
+    store.dnd.exceptions = config.dnd.exceptions.clone();
+    store.dnd.schedule = config.dnd.schedule.map(Into::into);
+    store.rules = config.rules.clone();
+    drop(store);
+    history.lock().unwrap().set_retention(config.history.into());
+}
+
+/// Read the configuration, falling back to the defaults if it is invalid.
+fn load_config(path: &Path) -> Config {
+    Config::load(path).unwrap_or_else(|err| {
+        tracing::error!("{err}");
+        tracing::error!("using the default config");
+        Config::default()
+    })
+}
+
+/// Reads the configuration file and applies it everywhere.
+#[derive(Clone)]
+struct Reloader {
+    path: Option<PathBuf>,
+    store: SharedStore,
+    history: SharedHistory,
+    tx: UnboundedSender<Message>,
+    /// Notified after every reload.
+    reloaded: Arc<Notify>,
+}
+
+impl Reloader {
+    /// Reload the configuration. If it is invalid, the previous one stays in
+    /// effect and the error says what is wrong.
+    fn reload(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Err("the daemon was started without a config file".to_owned());
+        };
+        let config = Config::load(path)?;
+        apply_config(&config, &self.store, &self.history);
+        self.reloaded.notify_one();
+        if self.tx.send(Message::Reload(Arc::new(config))).is_err() {
+            tracing::error!("wayland event loop is gone, config will not be applied");
+        }
+        Ok(())
+    }
+}
+
+/// Reload the configuration when the file changes or on SIGHUP.
+async fn watch_config(path: PathBuf, reloader: Reloader) -> io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    // the directory is watched, as editors tend to replace the file
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    let inotify = Inotify::init()?;
+    let mask =
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM | WatchMask::DELETE;
+    let mut changes = match inotify.watches().add(dir, mask) {
+        Ok(_) => Some(inotify.into_event_stream([0; 1024])?),
+        Err(err) => {
+            tracing::info!(
+                "not watching {} for changes: {err}, reload with SIGHUP or `uwu-notify reload`",
+                dir.display()
+            );
+            None
+        }
+    };
+
+    loop {
+        let change = OptionFuture::from(changes.as_mut().map(|changes| changes.next()));
+        tokio::select! {
+            _ = hangup.recv() => tracing::info!("reloading config on SIGHUP"),
+            Some(Some(event)) = change => {
+                if event?.name.as_deref() != Some(name) {
+                    continue;
+                }
+                tracing::info!("config file changed, reloading");
+            }
+        }
+        if let Err(err) = reloader.reload() {
+            tracing::error!("{err}");
+            tracing::error!("keeping the previous config");
+        }
+    }
+}
+
+/// Emit the signals requested by the Wayland event loop and the servers.
+async fn emit_signals(
+    ctx: SignalContext<'static>,
+    control: SignalContext<'static>,
+    mut signals: UnboundedReceiver<Signal>,
+) {
+    while let Some(signal) = signals.recv().await {
+        let result = match &signal {
+            Signal::NotificationAdded(id) => ControlServer::notification_added(&control, *id).await,
+            Signal::NotificationClosed(id, reason) => {
+                NotificationServer::notification_closed(&ctx, *id, *reason as u32).await
+            }
+            Signal::ActionInvoked(id, key) => {
+                NotificationServer::action_invoked(&ctx, *id, key.clone()).await
+            }
+            Signal::ActivationToken(id, token) => {
+                NotificationServer::activation_token(&ctx, *id, token.clone()).await
+            }
+            Signal::NotificationReplied(id, text) => {
+                NotificationServer::notification_replied(&ctx, *id, text.clone()).await
+            }
+        };
+        if let Err(err) = result {
+            tracing::error!("failed to emit {signal:?}: {err}");
+        }
+    }
+}
+
+/// Become the notification daemon, returning the stream telling us when
+/// another one replaced us.
+async fn request_name(
+    conn: &Connection,
+    replace: bool,
+) -> color_eyre::Result<NameLostStream<'static>> {
+    let dbus = DBusProxy::new(conn).await?;
+    // subscribe first so that losing the name right away is not missed
+    let lost = dbus.receive_name_lost_with_args(&[(0, NAME)]).await?;
+    let owner = owner(&dbus).await;
+
+    let mut flags = RequestNameFlags::AllowReplacement | RequestNameFlags::DoNotQueue;
+    if replace {
+        flags |= RequestNameFlags::ReplaceExisting;
+    }
+    // without queueing, we own the name unless it is taken
+    match conn.request_name_with_flags(NAME, flags).await {
+        Ok(_) => {}
+        Err(zbus::Error::NameTaken) => {
+            let owner = owner.as_deref().unwrap_or("another notification daemon");
+            if replace {
+                bail!("{owner} owns {NAME} and does not let it be replaced, stop it first");
+            }
+            bail!("{owner} already owns {NAME}, run with --replace to take over");
+        }
+        Err(err) => return Err(err.into()),
+    }
+    if let Some(owner) = owner.filter(|_| replace) {
+        tracing::info!("took over {NAME} from {owner}");
+    }
+    Ok(lost)
+}
+
+/// The process currently owning the bus name, like `mako (pid 1234)`, if
+/// there is one and the bus tells.
+async fn owner(dbus: &DBusProxy<'_>) -> Option<String> {
+    let owner = dbus
+        .get_name_owner(BusName::try_from(NAME).ok()?)
+        .await
+        .ok()?;
+    let pid = dbus
+        .get_connection_unix_process_id(owner.as_ref().into())
+        .await
+        .ok()?;
+    match fs::read_to_string(format!("/proc/{pid}/comm")) {
+        Ok(comm) => Some(format!("{} (pid {pid})", comm.trim_end())),
+        Err(_) => Some(format!("pid {pid}")),
+    }
+}
+
+/// Close every notification and stop showing anything once another daemon
+/// replaced us.
+async fn give_up_name(
+    mut lost: NameLostStream<'static>,
+    ctx: SignalContext<'static>,
+    store: SharedStore,
+    tx: UnboundedSender<Message>,
+) {
+    if lost.next().await.is_none() {
+        return;
+    }
+    tracing::info!("another daemon took over {NAME}");
+    let ids = store.lock().unwrap().clear();
+    // emitted here rather than through `emit_signals`, so that they are sent
+    // before the daemon exits
+    for id in ids {
+        let reason = CloseReason::Undefined as u32;
+        if let Err(err) = NotificationServer::notification_closed(&ctx, id, reason).await {
+            tracing::error!("failed to emit NotificationClosed for {id}: {err}");
+        }
+    }
+    if tx.send(Message::Shutdown).is_err() {
+        tracing::error!("wayland event loop is gone, cannot shut it down");
+    }
+}
+
+/// Stand in for the Wayland side without showing anything, only closing
+/// notifications as they expire.
+async fn show_nothing(
+    store: SharedStore,
+    mut rx: UnboundedReceiver<Message>,
+    signals: UnboundedSender<Signal>,
+) -> color_eyre::Result<()> {
+    let mut notifications = Notifications::new(store, signals);
+    loop {
+        let expiry = notifications.store.lock().unwrap().next_expiry();
+        let expire = tokio::time::sleep_until(expiry.unwrap_or_else(Instant::now).into());
+        tokio::select! {
+            message = rx.recv() => match message {
+                Some(Message::Shutdown) => {
+                    tracing::info!("shutting down");
+                    // normally closed on the D-Bus side already
+                    notifications.close_all(CloseReason::Undefined);
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => return Ok(()),
+            },
+            _ = expire, if expiry.is_some() => {
+                notifications.expire(None);
+            }
+        }
+    }
+}
+
+/// Serve notifications until the frontend gives up.
+///
+/// Everything runs on the current thread, see [`wayland::main`].
+pub async fn run(options: Options) -> color_eyre::Result<()> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let (signal_tx, signal_rx) = tokio::sync::mpsc::unbounded_channel();
+    let store = Store::shared();
+    let history = History::load(options.history).shared();
+    let config_path = options.config;
+    let config = config_path
+        .as_deref()
+        .map_or_else(Config::default, load_config);
+    apply_config(&config, &store, &history);
+
+    let reloader = Reloader {
+        path: config_path.clone(),
+        store: store.clone(),
+        history: history.clone(),
+        tx: tx.clone(),
+        reloaded: Arc::new(Notify::new()),
+    };
+    let reloaded = reloader.reloaded.clone();
+    if let Some(path) = config_path {
+        let watch = watch_config(path, reloader.clone());
+        tokio::spawn(async move {
+            if let Err(err) = watch.await {
+                tracing::error!("stopped watching the config: {err}");
+            }
+        });
+    }
+
+    let server = NotificationServer {
+        store: store.clone(),
+        history: history.clone(),
+        tx: tx.clone(),
+        signals: signal_tx.clone(),
+    };
+    let builder = match &options.address {
+        Some(address) => ConnectionBuilder::address(&**address)?,
+        None => ConnectionBuilder::session()?,
+    };
+    let conn = builder
+        .serve_at("/org/freedesktop/Notifications", server)?
+        .serve_at(
+            "/rs/uwu/Notify",
+            HistoryServer {
+                history: history.clone(),
+            },
+        )?
+        .serve_at(
+            "/rs/uwu/Notify",
+            ControlServer {
+                store: store.clone(),
+                history,
+                tx: reloader.tx.clone(),
+                signals: signal_tx.clone(),
+                reloader,
+            },
+        )?
+        .build()
+        .await?;
+    let ctx = SignalContext::new(&conn, "/org/freedesktop/Notifications")?.into_owned();
+    let lost = request_name(&conn, options.replace).await?;
+    tokio::spawn(give_up_name(lost, ctx.clone(), store.clone(), tx));
+    let control = SignalContext::new(&conn, "/rs/uwu/Notify")?;
+    tokio::spawn(emit_signals(ctx, control.into_owned(), signal_rx));
+    let schedule_conn = conn.clone();
+    tokio::spawn(async move {
+        if let Err(err) = follow_schedule(schedule_conn, reloaded).await {
+            tracing::error!("stopped following the do not disturb schedule: {err}");
+        }
+    });
+
+    let config = Arc::new(config);
+    match options.frontend {
+        Frontend::Wayland => wayland::main(config, store, rx, signal_tx).await,
+        Frontend::Null => show_nothing(store, rx, signal_tx).await,
+    }
+}
@@ -0,0 +1,169 @@
+//! Do Not Disturb: keeping notifications from popping up for a while.
+
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime};
+
+use crate::hints::Urgency;
+use crate::notification::Notification;
+
+/// A time of day window, which may wrap around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // e.g. 22:00 to 07:00
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long after `time` the window opens or closes next.
+    pub fn until_edge(&self, time: NaiveTime) -> Duration {
+        let day = chrono::Duration::days(1);
+        [self.start, self.end]
+            .into_iter()
+            .map(|edge| {
+                let until = edge.signed_duration_since(time);
+                if until <= chrono::Duration::zero() {
+                    until + day
+                } else {
+                    until
+                }
+            })
+            .min()
+            .and_then(|until| until.to_std().ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub struct Dnd {
+    /// Whether Do Not Disturb is in effect, see [`Dnd::tick`] for the schedule.
+    pub enabled: bool,
+    /// Whether critical notifications still pop up.
+    pub allow_critical: bool,
+    /// Applications, by name or desktop entry, whose notifications still pop up.
+    pub exceptions: Vec<String>,
+    /// When Do Not Disturb switches itself on every day.
+    pub schedule: Option<Schedule>,
+    /// Whether the schedule window was open on the last [`Dnd::tick`].
+    in_schedule: bool,
+}
+
+impl Default for Dnd {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_critical: true,
+            exceptions: Vec::new(),
+            schedule: None,
+            in_schedule: false,
+        }
+    }
+}
+
+impl Dnd {
+    /// Follow the schedule, switching on when its window opens and off when it
+    /// closes. In between, the user is free to switch it either way.
+    ///
+    /// Returns whether [`Dnd::enabled`] changed.
+    pub fn tick(&mut self) -> bool {
+        let Some(schedule) = self.schedule else {
+            return false;
+        };
+        let inside = schedule.contains(Local::now().time());
+        if inside == self.in_schedule {
+            return false;
+        }
+        self.in_schedule = inside;
+        let changed = self.enabled != inside;
+        self.enabled = inside;
+        changed
+    }
+
+    /// Whether `notification` should be kept from popping up.
+    pub fn suppresses(&self, notification: &Notification) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if self.allow_critical && notification.hints.urgency == Urgency::Critical {
+            return false;
+        }
+        let app = [
+            Some(&notification.app_name),
+            notification.hints.desktop_entry.as_ref(),
+        ];
+        !self
+            .exceptions
+            .iter()
+            .any(|exception| app.contains(&Some(exception)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn schedule(start: NaiveTime, end: NaiveTime) -> Schedule {
+        Schedule { start, end }
+    }
+
+    #[test]
+    fn within_a_day() {
+        let work = schedule(time(9, 0), time(17, 0));
+        assert!(work.contains(time(9, 0)));
+        assert!(work.contains(time(12, 30)));
+        assert!(!work.contains(time(17, 0)));
+        assert!(!work.contains(time(8, 59)));
+        assert_eq!(work.until_edge(time(8, 0)), Duration::from_secs(60 * 60));
+        assert_eq!(work.until_edge(time(16, 30)), Duration::from_secs(30 * 60));
+    }
+
+    #[test]
+    fn wrapping_past_midnight() {
+        let night = schedule(time(22, 0), time(7, 0));
+        assert!(night.contains(time(22, 0)));
+        assert!(night.contains(time(23, 59)));
+        assert!(night.contains(time(0, 0)));
+        assert!(night.contains(time(6, 59)));
+        assert!(!night.contains(time(7, 0)));
+        assert!(!night.contains(time(12, 0)));
+        assert!(!night.contains(time(21, 59)));
+        // from before midnight to the end in the morning
+        assert_eq!(
+            night.until_edge(time(23, 0)),
+            Duration::from_secs(8 * 60 * 60)
+        );
+        assert_eq!(
+            night.until_edge(time(12, 0)),
+            Duration::from_secs(10 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn at_an_edge_waits_a_day_for_it() {
+        let night = schedule(time(22, 0), time(7, 0));
+        // the start is a day away, the end only 9 hours
+        assert_eq!(
+            night.until_edge(time(22, 0)),
+            Duration::from_secs(9 * 60 * 60)
+        );
+        let empty = schedule(time(8, 0), time(8, 0));
+        assert!(!empty.contains(time(8, 0)));
+        assert_eq!(
+            empty.until_edge(time(8, 0)),
+            Duration::from_secs(24 * 60 * 60)
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Drawing notifications into an image, without a compositor or a GPU.
+//!
+//! The cards are laid out by the same code the daemon uses and painted by
+//! [`crate::raster`], so layout changes can be checked by snapshot tests and
+//! with `uwu-notify render`.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::bail;
+use serde::Deserialize;
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::config::Config;
+use crate::hints::Hints;
+use crate::icon::IconLoader;
+use crate::paint::Egui;
+use crate::raster::{Canvas, Painter};
+use crate::store::{NewNotification, Store};
+use crate::ui;
+
+/// The arguments of a `Notify` call, as read from JSON.
+///
+/// Hints are given as strings, integers or booleans, e.g.
+/// `{ "urgency": 2, "image-path": "/path/to/image.png" }`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Input {
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub actions: Vec<String>,
+    pub hints: HashMap<String, serde_json::Value>,
+}
+
+impl Input {
+    fn into_new(self) -> color_eyre::Result<NewNotification> {
+        let mut hints = HashMap::new();
+        for (name, value) in self.hints {
+            let value = match value {
+                serde_json::Value::Bool(v) => Value::Bool(v),
+                serde_json::Value::Number(v) => match v.as_i64() {
+                    Some(v) => Value::I64(v),
+                    None => Value::F64(v.as_f64().unwrap_or_default()),
+                },
+                serde_json::Value::String(v) => Value::from(v),
+                value => bail!("hint {name:?} is {value}, not a string, number or boolean"),
+            };
+            hints.insert(name, OwnedValue::from(value));
+        }
+        Ok(NewNotification {
+            app_name: self.app_name,
+            app_icon: self.app_icon,
+            summary: self.summary,
+            body: self.body,
+            actions: self.actions,
+            hints: Hints::parse(hints),
+            expire_timeout: -1,
+            style: Default::default(),
+            hidden: false,
+            skip_history: false,
+        })
+    }
+}
+
+/// Draw `notifications`, sent in this order, the way the daemon would show
+/// them with `config`.
+///
+/// The image is as wide as configured and as tall as the stack.
+pub fn render(notifications: Vec<Input>, config: &Config) -> color_eyre::Result<Canvas> {
+    let mut store = Store::default();
+    for input in notifications {
+        store.insert(0, input.into_new()?);
+    }
+    if !store.showing() {
+        bail!("there are no notifications to draw");
+    }
+
+    let mut egui = Egui::default();
+    ui::apply_config(&egui.egui_ctx, config);
+    let mut images = ui::Images::new(IconLoader::new(None));
+    let mut drafts = ui::Drafts::default();
+    let width = config.width.get();
+    let mut run = |height| {
+        let mut output = ui::Output::default();
+        egui.run([width, height], |egui_ctx| {
+            output = ui::show_store(egui_ctx, &store, config, &mut images, &mut drafts);
+        });
+        output
+    };
+    // like the surface, start out as small as possible and then fit the stack
+    let height = run(1).surface_height(config);
+    run(height);
+
+    let frame = egui.frame();
+    let mut painter = Painter::default();
+    for (id, image_delta) in &frame.textures_delta.set {
+        painter.set_texture(*id, image_delta);
+    }
+    let surface = config.colors.surface.map(|color| color.0);
+    let mut canvas = Canvas::new(0, 0);
+    canvas.clear(width, height, surface.unwrap_or_default());
+    painter.paint(&mut canvas, frame.pixels_per_point, &frame.primitives);
+    Ok(canvas)
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use egui::ColorImage;
+use serde::Deserialize;
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn from_level(level: i64) -> Self {
+        match level {
+            0 => Self::Low,
+            2 => Self::Critical,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// The hints passed to `Notify`, with the standard ones decoded.
+///
+/// Clients are not very careful about the types they send, so integers of any
+/// width are accepted where the spec asks for a particular one, and integers
+/// are accepted where it asks for a boolean.
+pub struct Hints {
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub transient: bool,
+    pub resident: bool,
+    pub suppress_sound: bool,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub action_icons: bool,
+    pub sender_pid: Option<i64>,
+    /// Raw pixels from `image-data`, or the deprecated `image_data` and `icon_data`.
+    pub image_data: Option<ColorImage>,
+    /// `image-path`, or the deprecated `image_path`: an icon name or a `file://` URI.
+    pub image_path: Option<String>,
+    /// The non-standard `value` hint: progress in percent, shown as a bar.
+    pub value: Option<u8>,
+    /// `x-canonical-private-synchronous` or `synchronous`: notifications with the
+    /// same tag replace each other, like consecutive volume changes.
+    pub synchronous: Option<String>,
+    /// `x-kde-reply-placeholder-text`: what to show in an empty inline reply field.
+    pub reply_placeholder: Option<String>,
+    /// Hints that are not decoded above, keyed by name.
+    pub other: HashMap<String, OwnedValue>,
+}
+
+impl Hints {
+    pub fn parse(mut raw: HashMap<String, OwnedValue>) -> Self {
+        let mut take = |key: &str| raw.remove(key);
+        let hints = Self {
+            urgency: take("urgency")
+                .and_then(|v| int(&v))
+                .map_or(Urgency::Normal, Urgency::from_level),
+            category: take("category").and_then(|v| string(&v)),
+            desktop_entry: take("desktop-entry").and_then(|v| string(&v)),
+            transient: take("transient").and_then(|v| boolean(&v)).unwrap_or(false),
+            resident: take("resident").and_then(|v| boolean(&v)).unwrap_or(false),
+            suppress_sound: take("suppress-sound")
+                .and_then(|v| boolean(&v))
+                .unwrap_or(false),
+            x: take("x").and_then(|v| int(&v)?.try_into().ok()),
+            y: take("y").and_then(|v| int(&v)?.try_into().ok()),
+            action_icons: take("action-icons")
+                .and_then(|v| boolean(&v))
+                .unwrap_or(false),
+            sender_pid: take("sender-pid").and_then(|v| int(&v)),
+            image_data: [take("image-data"), take("image_data"), take("icon_data")]
+                .into_iter()
+                .flatten()
+                .find_map(|v| image::from_hint(&v)),
+            image_path: [take("image-path"), take("image_path")]
+                .into_iter()
+                .flatten()
+                .find_map(|v| string(&v)),
+            value: take("value")
+                .and_then(|v| int(&v))
+                .map(|v| v.clamp(0, 100) as u8),
+            synchronous: [take("x-canonical-private-synchronous"), take("synchronous")]
+                .into_iter()
+                .flatten()
+                .find_map(|v| string(&v)),
+            reply_placeholder: take("x-kde-reply-placeholder-text").and_then(|v| string(&v)),
+            other: HashMap::new(),
+        };
+        Self {
+            other: raw,
+            ..hints
+        }
+    }
+}
+
+impl Hints {
+    /// The hints as decoded, under their standard names, for
+    /// `rs.uwu.Notify.Hints`. Of the image data only the size is kept, as
+    /// `image-size`, and hints that were not sent are left out.
+    pub fn decoded(&self) -> HashMap<String, OwnedValue> {
+        let mut hints = self
+            .other
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut insert = |key: &str, value: Value<'_>| {
+            hints.insert(key.to_owned(), value.into());
+        };
+        insert("urgency", Value::U8(self.urgency as u8));
+        insert("transient", Value::Bool(self.transient));
+        insert("resident", Value::Bool(self.resident));
+        insert("suppress-sound", Value::Bool(self.suppress_sound));
+        insert("action-icons", Value::Bool(self.action_icons));
+        let strings = [
+            ("category", &self.category),
+            ("desktop-entry", &self.desktop_entry),
+            ("image-path", &self.image_path),
+            ("synchronous", &self.synchronous),
+            ("x-kde-reply-placeholder-text", &self.reply_placeholder),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                insert(key, Value::from(value.as_str()));
+            }
+        }
+        if let Some(x) = self.x {
+            insert("x", Value::I32(x));
+        }
+        if let Some(y) = self.y {
+            insert("y", Value::I32(y));
+        }
+        if let Some(pid) = self.sender_pid {
+            insert("sender-pid", Value::I64(pid));
+        }
+        if let Some(value) = self.value {
+            insert("value", Value::U8(value));
+        }
+        if let Some(image) = &self.image_data {
+            let [width, height] = image.size.map(|side| side as i32);
+            insert("image-size", Value::from((width, height)));
+        }
+        hints
+    }
+}
+
+/// The value of a hint as text: strings as they are, numbers in decimal and
+/// booleans as `true` or `false`.
+pub fn text(value: &Value<'_>) -> Option<String> {
+    match unwrap_variant(value) {
+        Value::Bool(v) => Some(v.to_string()),
+        Value::F64(v) => Some(v.to_string()),
+        value => string(value).or_else(|| int(value).map(|v| v.to_string())),
+    }
+}
+
+/// Unwrap values that were sent as a variant inside the variant.
+fn unwrap_variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        value => value,
+    }
+}
+
+fn int(value: &Value<'_>) -> Option<i64> {
+    match *unwrap_variant(value) {
+        Value::U8(v) => Some(v.into()),
+        Value::I16(v) => Some(v.into()),
+        Value::U16(v) => Some(v.into()),
+        Value::I32(v) => Some(v.into()),
+        Value::U32(v) => Some(v.into()),
+        Value::I64(v) => Some(v),
+        Value::U64(v) => v.try_into().ok(),
+        _ => None,
+    }
+}
+
+fn boolean(value: &Value<'_>) -> Option<bool> {
+    match unwrap_variant(value) {
+        Value::Bool(v) => Some(*v),
+        value => int(value).map(|v| v != 0),
+    }
+}
+
+fn string(value: &Value<'_>) -> Option<String> {
+    match unwrap_variant(value) {
+        Value::Str(v) => Some(v.to_string()),
+        Value::ObjectPath(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
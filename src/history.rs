@@ -0,0 +1,381 @@
+//! Notifications that were shown, kept on disk after they are closed.
+//!
+//! The history lives in `$XDG_STATE_HOME/uwu-notify/history.jsonl`, one entry
+//! per line, oldest first. New and replaced entries are appended, a later line
+//! replacing an earlier one for the same notification, and the file is only
+//! rewritten once enough of it is outdated.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+use crate::markup::Markup;
+use crate::notification::Notification;
+
+pub type SharedHistory = Arc<Mutex<History>>;
+
+/// How many outdated lines the file may have beyond one per entry before it
+/// is rewritten.
+const SLACK: usize = 100;
+
+/// A notification as remembered in the history.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct Entry {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    /// The body as sent, markup included.
+    pub body: String,
+    /// 0 for low, 1 for normal and 2 for critical, like the `urgency` hint.
+    pub urgency: u8,
+    /// The `category` hint, empty if there was none.
+    pub category: String,
+    /// When the notification was first shown, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Entry {
+    pub fn new(notification: &Notification) -> Self {
+        Self {
+            id: notification.id,
+            app_name: notification.app_name.clone(),
+            app_icon: notification.app_icon.clone(),
+            summary: notification.summary.clone(),
+            body: notification.body.clone(),
+            urgency: notification.hints.urgency as u8,
+            category: notification.hints.category.clone().unwrap_or_default(),
+            timestamp: unix_time(notification.created_at),
+        }
+    }
+
+    /// Whether this is the entry of a live notification.
+    pub fn describes(&self, notification: &Notification) -> bool {
+        self.id == notification.id && self.timestamp == unix_time(notification.created_at)
+    }
+
+    /// Whether both entries are of the same notification.
+    fn same(&self, other: &Entry) -> bool {
+        self.id == other.id && self.timestamp == other.timestamp
+    }
+
+    /// Whether `query` occurs in the app name, summary or body, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [
+            &*self.app_name,
+            &*self.summary,
+            &Markup::parse(&self.body).text(),
+        ]
+        .into_iter()
+        .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// How much history to keep. `None` means no limit.
+#[derive(Debug)]
+pub struct Retention {
+    pub max_entries: Option<usize>,
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_entries: Some(1000),
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        }
+    }
+}
+
+pub struct History {
+    /// Oldest first.
+    entries: Vec<Entry>,
+    path: Option<PathBuf>,
+    /// How many lines the file has, outdated ones included.
+    lines: usize,
+    retention: Retention,
+}
+
+impl History {
+    /// Load the history from `path`, starting out empty if it cannot be read.
+    ///
+    /// Without a path, the history is only kept in memory.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let lines = match path.as_deref().map(read) {
+            Some(Ok(lines)) => lines,
+            Some(Err(err)) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Some(Err(err)) => {
+                tracing::error!("failed to read notification history: {err}");
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        let mut history = Self {
+            entries: Vec::new(),
+            path,
+            lines: lines.len(),
+            retention: Retention::default(),
+        };
+        for entry in lines {
+            history.insert(entry);
+        }
+        history.prune();
+        history.compact();
+        history
+    }
+
+    /// Change how much history is kept, dropping what is too much already.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        let len = self.entries.len();
+        self.prune();
+        if self.entries.len() != len {
+            self.compact();
+        }
+    }
+
+    pub fn shared(self) -> SharedHistory {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Remember a notification that was just shown or replaced.
+    ///
+    /// Replacing a notification updates its entry rather than adding another.
+    /// Transient notifications are left for the caller to skip.
+    pub fn record(&mut self, entry: Entry) {
+        self.append(&entry);
+        self.insert(entry);
+        self.prune();
+        self.compact();
+    }
+
+    /// Add an entry, or update the one of the same notification.
+    fn insert(&mut self, entry: Entry) {
+        match self.entries.iter_mut().rev().find(|e| e.same(&entry)) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Iterate over the entries, newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().rev()
+    }
+
+    /// Up to `limit` entries, newest first, skipping the `offset` newest.
+    pub fn list(&self, offset: usize, limit: usize) -> Vec<Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Entries matching `query`, newest first, see [`Entry::matches`].
+    pub fn search(&self, query: &str) -> Vec<Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.matches(query))
+            .cloned()
+            .collect()
+    }
+
+    /// The newest entry with the given id.
+    ///
+    /// Ids are only unique among live notifications, so older entries may share it.
+    pub fn get(&self, id: u32) -> Option<&Entry> {
+        self.entries.iter().rev().find(|entry| entry.id == id)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.save();
+    }
+
+    /// Add a line for `entry` to the file, cheaper than rewriting it.
+    fn append(&mut self, entry: &Entry) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        match append(path, entry) {
+            Ok(()) => self.lines += 1,
+            Err(err) => tracing::error!("failed to add to notification history: {err}"),
+        }
+    }
+
+    /// Rewrite the file if too much of it is outdated.
+    fn compact(&mut self) {
+        if self.lines > self.entries.len() + SLACK {
+            self.save();
+        }
+    }
+
+    /// Drop the entries that are too old or too many.
+    fn prune(&mut self) {
+        if let Some(max_age) = self.retention.max_age {
+            let oldest = unix_time(SystemTime::now()).saturating_sub(max_age.as_secs());
+            self.entries.retain(|entry| entry.timestamp >= oldest);
+        }
+        if let Some(max) = self.retention.max_entries {
+            let excess = self.entries.len().saturating_sub(max);
+            self.entries.drain(..excess);
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        match write(path, &self.entries) {
+            Ok(()) => self.lines = self.entries.len(),
+            Err(err) => tracing::error!("failed to write notification history: {err}"),
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/uwu-notify/history.jsonl`, or `~/.local/state/uwu-notify/history.jsonl`.
+pub fn path() -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(state.join("uwu-notify").join("history.jsonl"))
+}
+
+fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let file = BufReader::new(fs::File::open(path)?);
+    let mut entries = Vec::new();
+    for (number, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => tracing::warn!("skipping line {} of {}: {err}", number + 1, path.display()),
+        }
+    }
+    Ok(entries)
+}
+
+fn append(path: &Path, entry: &Entry) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    // one write, so that lines of concurrent writers do not interleave
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+/// Replace the history file, going through a temporary file so that a crash
+/// of the daemon never leaves it half written.
+fn write(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+    for entry in entries {
+        serde_json::to_writer(&mut file, entry)?;
+        file.write_all(b"\n")?;
+    }
+    file.into_inner()?;
+    fs::rename(tmp, path)
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::hints::Hints;
+    use crate::store::{NewNotification, Store};
+
+    fn new(summary: &str) -> NewNotification {
+        NewNotification {
+            app_name: "tests".to_owned(),
+            app_icon: String::new(),
+            summary: summary.to_owned(),
+            body: String::new(),
+            actions: Vec::new(),
+            hints: Hints::parse(HashMap::new()),
+            expire_timeout: 0,
+            style: Default::default(),
+            hidden: false,
+            skip_history: false,
+        }
+    }
+
+    /// A history file of its own for every test.
+    fn path(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("uwu-notify-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("history.jsonl")
+    }
+
+    fn lines(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn replacing_appends_and_loading_merges() {
+        let path = path("merge");
+        let mut store = Store::default();
+        let mut history = History::load(Some(path.clone()));
+        let id = store.insert(0, new("Downloading"));
+        history.record(Entry::new(store.get(id).unwrap()));
+        store.insert(id, new("Downloaded"));
+        history.record(Entry::new(store.get(id).unwrap()));
+        let other = store.insert(0, new("Other"));
+        history.record(Entry::new(store.get(other).unwrap()));
+        assert_eq!(lines(&path), 3);
+
+        let loaded = History::load(Some(path.clone()));
+        let summaries = loaded.iter().map(|e| &*e.summary).collect::<Vec<_>>();
+        assert_eq!(summaries, ["Other", "Downloaded"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn outdated_lines_are_compacted() {
+        let path = path("compact");
+        let mut store = Store::default();
+        let mut history = History::load(Some(path.clone()));
+        let id = store.insert(0, new("Volume 0%"));
+        history.record(Entry::new(store.get(id).unwrap()));
+        for volume in 1..=3 * SLACK {
+            store.insert(id, new(&format!("Volume {volume}%")));
+            history.record(Entry::new(store.get(id).unwrap()));
+        }
+        assert!(lines(&path) <= 1 + SLACK);
+
+        let loaded = History::load(Some(path.clone()));
+        let summaries = loaded.iter().map(|e| &*e.summary).collect::<Vec<_>>();
+        assert_eq!(summaries, [format!("Volume {}%", 3 * SLACK)]);
+
+        history.clear();
+        assert_eq!(lines(&path), 0);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
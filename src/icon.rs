@@ -0,0 +1,596 @@
+//! Icon lookup following the freedesktop icon theme specification.
+//!
+//! See <https://specifications.freedesktop.org/icon-theme-spec/latest/>.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use egui::{Color32, ColorImage};
+use resvg::{tiny_skia, usvg};
+
+/// How many decoded icons to keep around.
+const CACHE_SIZE: usize = 64;
+
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Resolves and decodes icons, caching the results.
+pub struct IconLoader {
+    /// `$XDG_DATA_DIRS/icons` and friends, in order of preference.
+    base_dirs: Vec<PathBuf>,
+    /// The user's theme followed by everything it inherits from, ending with `hicolor`.
+    themes: Vec<Theme>,
+    cache: HashMap<(String, u32, u32), Option<ColorImage>>,
+}
+
+struct Theme {
+    name: String,
+    directories: Vec<Directory>,
+}
+
+#[derive(Debug)]
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    kind: DirectoryKind,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DirectoryKind {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+impl IconLoader {
+    /// Create a loader for the named theme, `None` to use the GTK setting.
+    pub fn new(theme: Option<&str>) -> Self {
+        let base_dirs = base_dirs();
+        let theme = theme.map(str::to_owned).or_else(gtk_theme);
+        let mut themes = Vec::new();
+        let mut pending = theme.into_iter().collect::<Vec<_>>();
+        while let Some(name) = pending.pop() {
+            if themes.iter().any(|t: &Theme| t.name == name) || name == "hicolor" {
+                continue;
+            }
+            if let Some((theme, inherits)) = Theme::load(&base_dirs, &name) {
+                themes.push(theme);
+                // keep the order of `Inherits`, depth first
+                pending.extend(inherits.into_iter().rev());
+            }
+        }
+        themes.extend(Theme::load(&base_dirs, "hicolor").map(|(theme, _)| theme));
+        tracing::debug!(
+            themes = ?themes.iter().map(|t| &t.name).collect::<Vec<_>>(),
+            "loaded icon themes"
+        );
+
+        Self {
+            base_dirs,
+            themes,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Load an icon given as a `file://` URI, an absolute path or a themed icon name,
+    /// `size` logical pixels big on a surface of the given scale, so
+    /// `size * scale` physical pixels.
+    pub fn load(&mut self, icon: &str, size: u32, scale: u32) -> Option<ColorImage> {
+        if icon.is_empty() {
+            return None;
+        }
+        let key = (icon.to_owned(), size, scale);
+        if let Some(image) = self.cache.get(&key) {
+            return image.clone();
+        }
+
+        let path = match icon.strip_prefix("file://") {
+            Some(path) => Some(PathBuf::from(percent_decode(path))),
+            None if icon.starts_with('/') => Some(PathBuf::from(icon)),
+            None => self.find(icon, size, scale),
+        };
+        let image = path.and_then(|path| {
+            let image = decode(&path, size * scale);
+            if image.is_none() {
+                tracing::warn!("failed to decode icon {}", path.display());
+            }
+            image
+        });
+
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+        self.cache.insert(key, image.clone());
+        image
+    }
+
+    /// Forget the icons loaded for any other scale than the given one.
+    pub fn retain_scale(&mut self, scale: u32) {
+        self.cache.retain(|&(_, _, cached), _| cached == scale);
+    }
+
+    /// Find the file of a themed icon.
+    fn find(&self, name: &str, size: u32, scale: u32) -> Option<PathBuf> {
+        self.themes
+            .iter()
+            .find_map(|theme| self.lookup(theme, name, size, scale))
+            .or_else(|| self.lookup_fallback(name))
+    }
+
+    fn lookup(&self, theme: &Theme, name: &str, size: u32, scale: u32) -> Option<PathBuf> {
+        let exact = theme
+            .directories
+            .iter()
+            .filter(|dir| dir.matches_size(size, scale))
+            .flat_map(|dir| self.candidates(theme, dir, name))
+            .find(|path| path.is_file());
+        if exact.is_some() {
+            return exact;
+        }
+
+        theme
+            .directories
+            .iter()
+            .flat_map(|dir| {
+                self.candidates(theme, dir, name)
+                    .map(move |path| (dir, path))
+            })
+            .filter(|(_, path)| path.is_file())
+            .min_by_key(|(dir, _)| dir.size_distance(size, scale))
+            .map(|(_, path)| path)
+    }
+
+    /// The paths an icon could have in a directory of a theme.
+    fn candidates<'a>(
+        &'a self,
+        theme: &'a Theme,
+        dir: &'a Directory,
+        name: &'a str,
+    ) -> impl Iterator<Item = PathBuf> + 'a {
+        self.base_dirs.iter().flat_map(move |base| {
+            EXTENSIONS.iter().map(move |ext| {
+                base.join(&theme.name)
+                    .join(&dir.path)
+                    .join(format!("{name}.{ext}"))
+            })
+        })
+    }
+
+    fn lookup_fallback(&self, name: &str) -> Option<PathBuf> {
+        self.base_dirs
+            .iter()
+            .map(PathBuf::as_path)
+            .chain([Path::new("/usr/share/pixmaps")])
+            .flat_map(|dir| {
+                EXTENSIONS
+                    .iter()
+                    .map(move |ext| dir.join(format!("{name}.{ext}")))
+            })
+            .find(|path| path.is_file())
+    }
+}
+
+impl Theme {
+    /// Load a theme by name, along with the names of the themes it inherits from.
+    fn load(base_dirs: &[PathBuf], name: &str) -> Option<(Self, Vec<String>)> {
+        let index = base_dirs
+            .iter()
+            .find_map(|dir| fs::read_to_string(dir.join(name).join("index.theme")).ok())?;
+        let sections = parse_ini(&index);
+        let theme = sections.get("Icon Theme")?;
+
+        let list = |key: &str| {
+            theme
+                .get(key)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_owned)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        let inherits = list("Inherits");
+        let mut directories = list("Directories");
+        directories.extend(list("ScaledDirectories"));
+
+        let directories = directories
+            .into_iter()
+            .filter_map(|path| {
+                let section = sections.get(path.as_str())?;
+                let int = |key: &str| section.get(key).and_then(|v| v.parse::<u32>().ok());
+                let size = int("Size")?;
+                Some(Directory {
+                    size,
+                    scale: int("Scale").unwrap_or(1),
+                    kind: match section.get("Type").map(String::as_str) {
+                        Some("Fixed") => DirectoryKind::Fixed,
+                        Some("Scalable") => DirectoryKind::Scalable,
+                        _ => DirectoryKind::Threshold,
+                    },
+                    min_size: int("MinSize").unwrap_or(size),
+                    max_size: int("MaxSize").unwrap_or(size),
+                    threshold: int("Threshold").unwrap_or(2),
+                    path,
+                })
+            })
+            .collect();
+
+        let theme = Self {
+            name: name.to_owned(),
+            directories,
+        };
+        Some((theme, inherits))
+    }
+}
+
+impl Directory {
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+        match self.kind {
+            DirectoryKind::Fixed => self.size == size,
+            DirectoryKind::Scalable => (self.min_size..=self.max_size).contains(&size),
+            DirectoryKind::Threshold => (self.size.saturating_sub(self.threshold)
+                ..=self.size + self.threshold)
+                .contains(&size),
+        }
+    }
+
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let wanted = size * scale;
+        let (min, max) = match self.kind {
+            DirectoryKind::Fixed => (self.size, self.size),
+            DirectoryKind::Scalable => (self.min_size, self.max_size),
+            DirectoryKind::Threshold => (
+                self.size.saturating_sub(self.threshold),
+                self.size + self.threshold,
+            ),
+        };
+        let (min, max) = (min * self.scale, max * self.scale);
+        if wanted < min {
+            min - wanted
+        } else {
+            wanted.saturating_sub(max)
+        }
+    }
+}
+
+/// The directories to look for icon themes in, in order of preference.
+fn base_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if let Some(home) = &home {
+        dirs.push(home.join(".icons"));
+    }
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => dirs.push(PathBuf::from(data_home).join("icons")),
+        None => dirs.extend(home.map(|home| home.join(".local/share/icons"))),
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+    dirs.extend(data_dirs.split(':').map(|dir| Path::new(dir).join("icons")));
+    dirs
+}
+
+/// Read the icon theme configured for GTK applications.
+fn gtk_theme() -> Option<String> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    ["gtk-4.0", "gtk-3.0"].into_iter().find_map(|gtk| {
+        let settings = fs::read_to_string(config.join(gtk).join("settings.ini")).ok()?;
+        parse_ini(&settings)
+            .get("Settings")?
+            .get("gtk-icon-theme-name")
+            .cloned()
+    })
+}
+
+/// A minimal parser for the ini-like format of `index.theme` files.
+fn parse_ini(contents: &str) -> HashMap<&str, HashMap<&str, String>> {
+    let mut sections = HashMap::<_, HashMap<_, _>>::new();
+    let mut current = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(section);
+            sections.entry(section).or_default();
+        } else if let (Some(section), Some((key, value))) = (current, line.split_once('=')) {
+            let value = value.trim().trim_matches('"').to_owned();
+            sections
+                .entry(section)
+                .or_default()
+                .insert(key.trim(), value);
+        }
+    }
+    sections
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Decode a PNG or SVG file, scaled to fit in a `size` by `size` square.
+fn decode(path: &Path, size: u32) -> Option<ColorImage> {
+    let data = fs::read(path).ok()?;
+    if data.starts_with(b"\x89PNG") {
+        decode_png(&data).and_then(|image| resize(image, size))
+    } else {
+        decode_svg(&data, size)
+    }
+}
+
+fn decode_png(data: &[u8]) -> Option<ColorImage> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let buf = &buf[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf
+            .chunks_exact(4)
+            .map(|p| Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+            .collect(),
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .map(|p| Color32::from_rgb(p[0], p[1], p[2]))
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .map(|p| Color32::from_rgba_unmultiplied(p[0], p[0], p[0], p[1]))
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().map(|&p| Color32::from_gray(p)).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some(ColorImage {
+        size: [info.width as usize, info.height as usize],
+        pixels,
+    })
+}
+
+/// Scale an image up or down to fit in a `size` by `size` square, themes
+/// rarely having every icon at every size.
+fn resize(image: ColorImage, size: u32) -> Option<ColorImage> {
+    let [width, height] = image.size.map(|side| side as u32);
+    if width.max(height) == size {
+        return Some(image);
+    }
+    let scale = size as f32 / width.max(height) as f32;
+    let new_width = (width as f32 * scale).round().max(1.0) as u32;
+    let new_height = (height as f32 * scale).round().max(1.0) as u32;
+
+    // both are premultiplied RGBA
+    let data = image.pixels.iter().flat_map(|p| p.to_array()).collect();
+    let source = tiny_skia::Pixmap::from_vec(data, tiny_skia::IntSize::from_wh(width, height)?)?;
+    let mut pixmap = tiny_skia::Pixmap::new(new_width, new_height)?;
+    let paint = tiny_skia::PixmapPaint {
+        quality: tiny_skia::FilterQuality::Bicubic,
+        ..Default::default()
+    };
+    pixmap.draw_pixmap(
+        0,
+        0,
+        source.as_ref(),
+        &paint,
+        tiny_skia::Transform::from_scale(
+            new_width as f32 / width as f32,
+            new_height as f32 / height as f32,
+        ),
+        None,
+    );
+    let pixels = pixmap
+        .data()
+        .chunks_exact(4)
+        .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3]))
+        .collect();
+    Some(ColorImage {
+        size: [new_width as usize, new_height as usize],
+        pixels,
+    })
+}
+
+fn decode_svg(data: &[u8], size: u32) -> Option<ColorImage> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).ok()?;
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let width = (svg_size.width() * scale).round().max(1.0) as u32;
+    let height = (svg_size.height() * scale).round().max(1.0) as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    let pixels = pixmap
+        .data()
+        .chunks_exact(4)
+        .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3]))
+        .collect();
+    Some(ColorImage {
+        size: [width as usize, height as usize],
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    /// Point the base directories at a tree of themes shared by the tests, so
+    /// that they all set the environment to the same values.
+    fn loader(theme: &str) -> IconLoader {
+        static ROOT: OnceLock<PathBuf> = OnceLock::new();
+        let root = ROOT.get_or_init(|| {
+            let root = env::temp_dir().join(format!("uwu-notify-icons-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            let icons = root.join("data/icons");
+            let themes = [
+                (
+                    "child",
+                    "Inherits=parent,other\nDirectories=48x48,48x48@2,scalable\n\n\
+                     [48x48]\nSize=48\nType=Fixed\n\n\
+                     [48x48@2]\nSize=48\nScale=2\nType=Fixed\n\n\
+                     [scalable]\nSize=48\nType=Scalable\nMinSize=16\nMaxSize=256\n",
+                ),
+                (
+                    "parent",
+                    "Inherits=grandparent\nDirectories=32x32\n\n[32x32]\nSize=32\n",
+                ),
+                // inheriting back must not loop
+                (
+                    "grandparent",
+                    "Inherits=child,hicolor\nDirectories=16x16\n\n[16x16]\nSize=16\n",
+                ),
+                ("other", "Directories=\n"),
+                (
+                    "hicolor",
+                    "Directories=48x48\n\n[48x48]\nSize=48\nType=Fixed\n",
+                ),
+            ];
+            for (name, index) in themes {
+                fs::create_dir_all(icons.join(name)).unwrap();
+                let index = format!("[Icon Theme]\nName={name}\n{index}");
+                fs::write(icons.join(name).join("index.theme"), index).unwrap();
+            }
+            for icon in [
+                "child/48x48/uwu-fixed.png",
+                "child/48x48@2/uwu-fixed.png",
+                "child/scalable/uwu-scalable.svg",
+                "parent/32x32/uwu-inherited.png",
+                "hicolor/48x48/uwu-hicolor.png",
+                "uwu-unthemed.png",
+            ] {
+                let path = icons.join(icon);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            env::set_var("HOME", root.join("home"));
+            env::set_var("XDG_DATA_HOME", root.join("home/data"));
+            env::set_var("XDG_DATA_DIRS", root.join("data"));
+            root
+        });
+        let loader = IconLoader::new(Some(theme));
+        assert_eq!(loader.base_dirs.last(), Some(&root.join("data/icons")));
+        loader
+    }
+
+    fn directory(kind: DirectoryKind, size: u32, scale: u32) -> Directory {
+        Directory {
+            path: String::new(),
+            size,
+            scale,
+            kind,
+            min_size: 16,
+            max_size: 64,
+            threshold: 2,
+        }
+    }
+
+    #[test]
+    fn inherits_depth_first_ending_with_hicolor() {
+        let names = |theme| {
+            let themes = loader(theme).themes.into_iter();
+            themes.map(|t| t.name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("child"),
+            ["child", "parent", "grandparent", "other", "hicolor"]
+        );
+        assert_eq!(names("missing"), ["hicolor"]);
+    }
+
+    #[test]
+    fn directory_sizes() {
+        let fixed = directory(DirectoryKind::Fixed, 48, 1);
+        assert!(fixed.matches_size(48, 1));
+        assert!(!fixed.matches_size(47, 1));
+        assert!(!fixed.matches_size(48, 2));
+        assert_eq!(fixed.size_distance(32, 1), 16);
+        assert_eq!(fixed.size_distance(32, 2), 16);
+
+        let scalable = directory(DirectoryKind::Scalable, 48, 1);
+        assert!(scalable.matches_size(16, 1) && scalable.matches_size(64, 1));
+        assert!(!scalable.matches_size(65, 1));
+        assert_eq!(scalable.size_distance(40, 1), 0);
+        assert_eq!(scalable.size_distance(80, 1), 16);
+
+        let threshold = directory(DirectoryKind::Threshold, 32, 2);
+        assert!(threshold.matches_size(30, 2) && threshold.matches_size(34, 2));
+        assert!(!threshold.matches_size(35, 2));
+        assert!(!threshold.matches_size(32, 1));
+        // compared in physical pixels, 30..=34 at scale 2 being 60..=68
+        assert_eq!(threshold.size_distance(64, 1), 0);
+        assert_eq!(threshold.size_distance(24, 2), 12);
+    }
+
+    #[test]
+    fn lookup() {
+        let loader = loader("child");
+        let icons = loader.base_dirs.last().unwrap();
+        let find = |name, size, scale| {
+            let path = loader.find(name, size, scale)?;
+            Some(path.strip_prefix(icons).unwrap().to_owned())
+        };
+        let path = |path: &str| Some(PathBuf::from(path));
+        assert_eq!(find("uwu-fixed", 48, 1), path("child/48x48/uwu-fixed.png"));
+        assert_eq!(
+            find("uwu-fixed", 48, 2),
+            path("child/48x48@2/uwu-fixed.png")
+        );
+        // closest size when none matches
+        assert_eq!(
+            find("uwu-fixed", 96, 1),
+            path("child/48x48@2/uwu-fixed.png")
+        );
+        assert_eq!(
+            find("uwu-scalable", 128, 1),
+            path("child/scalable/uwu-scalable.svg")
+        );
+        assert_eq!(
+            find("uwu-inherited", 48, 1),
+            path("parent/32x32/uwu-inherited.png")
+        );
+        assert_eq!(
+            find("uwu-hicolor", 48, 1),
+            path("hicolor/48x48/uwu-hicolor.png")
+        );
+        // not in any theme, but right in a base directory
+        assert_eq!(find("uwu-unthemed", 48, 1), path("uwu-unthemed.png"));
+        assert_eq!(find("uwu-missing", 48, 1), None);
+    }
+}
@@ -0,0 +1,152 @@
+use egui::{Color32, ColorImage};
+use zbus::zvariant::Value;
+
+/// Decode the raw pixel data of an `image-data` hint, signature `(iiibiiay)`.
+///
+/// The fields are width, height, rowstride, has_alpha, bits_per_sample,
+/// channels and the pixels themselves in RGB or RGBA byte order.
+pub fn from_hint(value: &Value<'_>) -> Option<ColorImage> {
+    let fields = match value.clone() {
+        // zvariant panics on structures with fewer fields than the tuple
+        Value::Structure(structure) if structure.fields().len() == 7 => {
+            <(i32, i32, i32, bool, i32, i32, Vec<u8>)>::try_from(structure)
+        }
+        _ => Err(zbus::zvariant::Error::IncorrectType),
+    };
+    let (width, height, rowstride, has_alpha, bits_per_sample, channels, data) = match fields {
+        Ok(fields) => fields,
+        Err(err) => {
+            tracing::warn!("image hint is not of type (iiibiiay): {err}");
+            return None;
+        }
+    };
+
+    let image = from_raw(
+        width,
+        height,
+        rowstride,
+        has_alpha,
+        bits_per_sample,
+        channels,
+        &data,
+    );
+    if image.is_none() {
+        tracing::warn!(
+            width,
+            height,
+            rowstride,
+            has_alpha,
+            bits_per_sample,
+            channels,
+            len = data.len(),
+            "ignoring malformed image hint"
+        );
+    }
+    image
+}
+
+fn from_raw(
+    width: i32,
+    height: i32,
+    rowstride: i32,
+    has_alpha: bool,
+    bits_per_sample: i32,
+    channels: i32,
+    data: &[u8],
+) -> Option<ColorImage> {
+    let width = usize::try_from(width).ok().filter(|&w| w > 0)?;
+    let height = usize::try_from(height).ok().filter(|&h| h > 0)?;
+    let rowstride = usize::try_from(rowstride).ok()?;
+    let channels = usize::try_from(channels).ok()?;
+    if bits_per_sample != 8 || channels != if has_alpha { 4 } else { 3 } {
+        return None;
+    }
+
+    let row_len = width.checked_mul(channels)?;
+    // the last row does not need to be padded to the full rowstride
+    let len = rowstride.checked_mul(height - 1)?.checked_add(row_len)?;
+    if rowstride < row_len || data.len() < len {
+        return None;
+    }
+
+    let pixels = data[..len]
+        .chunks(rowstride)
+        .flat_map(|row| row[..row_len].chunks_exact(channels))
+        .map(|px| match *px {
+            [r, g, b, a] => Color32::from_rgba_unmultiplied(r, g, b, a),
+            [r, g, b] => Color32::from_rgb(r, g, b),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    Some(ColorImage {
+        size: [width, height],
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hint(
+        width: i32,
+        height: i32,
+        rowstride: i32,
+        has_alpha: bool,
+        channels: i32,
+        data: Vec<u8>,
+    ) -> Value<'static> {
+        Value::from((width, height, rowstride, has_alpha, 8i32, channels, data))
+    }
+
+    #[test]
+    fn rgba() {
+        let data = vec![255, 0, 0, 255, 0, 0, 255, 128];
+        let image = from_hint(&hint(2, 1, 8, true, 4, data)).unwrap();
+        assert_eq!(image.size, [2, 1]);
+        assert_eq!(
+            image.pixels,
+            [
+                Color32::RED,
+                Color32::from_rgba_unmultiplied(0, 0, 255, 128)
+            ]
+        );
+    }
+
+    #[test]
+    fn rgb_with_padded_rows() {
+        // rows of one pixel padded to 4 bytes, except for the last one
+        let data = vec![1, 2, 3, 0, 4, 5, 6];
+        let image = from_hint(&hint(1, 2, 4, false, 3, data)).unwrap();
+        assert_eq!(image.size, [1, 2]);
+        assert_eq!(
+            image.pixels,
+            [Color32::from_rgb(1, 2, 3), Color32::from_rgb(4, 5, 6)]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let cases = [
+            // too little data
+            hint(2, 2, 8, true, 4, vec![0; 15]),
+            // rowstride shorter than a row
+            hint(2, 1, 4, true, 4, vec![0; 8]),
+            // channels not matching has_alpha
+            hint(1, 1, 3, true, 3, vec![0; 3]),
+            hint(0, 1, 4, true, 4, Vec::new()),
+            hint(-1, 1, 4, true, 4, vec![0; 4]),
+            Value::from((1i32, 1i32, 4i32, true, 16i32, 4i32, vec![0u8; 8])),
+        ];
+        for value in cases {
+            assert!(from_hint(&value).is_none(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn wrong_type() {
+        assert!(from_hint(&Value::from((1i32, 1i32))).is_none());
+        assert!(from_hint(&Value::from("image.png")).is_none());
+    }
+}
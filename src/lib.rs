@@ -0,0 +1,27 @@
+//! A notification daemon for Wayland compositors with the layer shell.
+//!
+//! The binary runs the daemon and the subcommands talking to it, the library
+//! also draws notifications without a compositor, see [`headless`].
+
+pub mod config;
+pub mod daemon;
+mod dnd;
+pub mod headless;
+mod hints;
+mod history;
+mod icon;
+mod image;
+mod markup;
+mod notification;
+mod paint;
+mod raster;
+mod render;
+mod rules;
+mod store;
+mod ui;
+mod wayland;
+
+pub use history::Entry;
+pub use hints::Urgency;
+pub use markup::Markup;
+pub use raster::Canvas;
@@ -1,88 +1,25 @@
-use std::collections::HashMap;
 use std::io;
-use std::sync::atomic::{AtomicU32, Ordering};
 
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::EnvFilter;
-use zbus::zvariant::OwnedValue;
-use zbus::{ConnectionBuilder, SignalContext};
+use uwu_notify::daemon::{self, Options};
 
-use egui_glow::EguiGlow;
-
-pub mod paint;
-mod wayland;
+mod cli;
 // mod window;
 
-struct NotificationServer {}
-
-static ID_COUNT: AtomicU32 = AtomicU32::new(1);
-
-#[zbus::dbus_interface(name = "org.freedesktop.Notifications")]
-impl NotificationServer {
-    /// CloseNotification method
-    fn close_notification(&self, id: u32) {}
-
-    /// GetCapabilities method
-    fn get_capabilities(&self) -> &'static [&'static str] {
-        &["body"]
-    }
-
-    /// GetServerInformation method
-    fn get_server_information(&self) -> (&'static str, &'static str, &'static str, &'static str) {
-        (
-            env!("CARGO_PKG_NAME"),
-            env!("CARGO_PKG_AUTHORS"),
-            env!("CARGO_PKG_VERSION"),
-            "1.2",
-        )
-    }
-
-    /// Notify method
-    #[tracing::instrument(skip(self))]
-    fn notify(
-        &self,
-        app_name: String,
-        replaces_id: u32,
-        app_icon: String,
-        summary: String,
-        body: String,
-        actions: Vec<String>,
-        hints: HashMap<String, OwnedValue>,
-        expire_timeout: i32,
-    ) -> u32 {
-        let id = if replaces_id == 0 {
-            ID_COUNT.fetch_add(1, Ordering::Relaxed)
-        } else {
-            replaces_id
-        };
-
-        id
-    }
-
-    /// ActionInvoked signal
-    #[dbus_interface(signal)]
-    async fn action_invoked(
-        ctx: &SignalContext<'_>,
-        id: u32,
-        action_key: String,
-    ) -> zbus::Result<()> {
-        Ok(())
-    }
-
-    /// NotificationClosed signal
-    #[dbus_interface(signal)]
-    async fn notification_closed(
-        ctx: &SignalContext<'_>,
-        id: u32,
-        reason: u32,
-    ) -> zbus::Result<()> {
-        Ok(())
-    }
-}
-
-#[tokio::main]
+// everything runs on one thread, see `wayland::main`
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
+    let args = cli::Args::parse();
+    if args.replace && args.command.is_some() {
+        let message = "--replace only applies to starting the daemon, not to subcommands";
+        cli::Args::command()
+            .error(ErrorKind::ArgumentConflict, message)
+            .exit();
+    }
     let filter = EnvFilter::from_default_env();
     let layer = tracing_tree::HierarchicalLayer::default()
         .with_writer(io::stderr)
@@ -93,7 +30,19 @@ async fn main() -> color_eyre::Result<()> {
     tracing::subscriber::set_global_default(log)?;
     tracing_log::log_tracer::LogTracer::init()?;
 
-    wayland::main()
+    match args.command {
+        Some(cli::Command::Remote(command)) => return cli::run(command, args.json).await,
+        Some(cli::Command::Render { input, out, config }) => {
+            return cli::render(&input, &out, config, args.json)
+        }
+        None => {}
+    }
+
+    let options = Options {
+        replace: args.replace,
+        ..Options::default()
+    };
+    daemon::run(options).await
 
     /*
 
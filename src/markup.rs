@@ -0,0 +1,301 @@
+//! The markup subset the notification spec allows in bodies.
+//!
+//! Supported are `<b>`, `<i>`, `<u>`, `<a href="...">`, `<img src="..." alt="..."/>`,
+//! of which only the alt text is shown, and the XML entity escapes.
+//! Unknown tags are ignored, but keep their content.
+
+/// A run of text sharing the same formatting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub link: Option<String>,
+}
+
+/// A parsed notification body.
+#[derive(Debug, Clone, Default)]
+pub struct Markup {
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug)]
+enum Tag {
+    Bold,
+    Italic,
+    Underline,
+    Link(String),
+    Other(String),
+}
+
+impl Tag {
+    fn name(&self) -> &str {
+        match self {
+            Tag::Bold => "b",
+            Tag::Italic => "i",
+            Tag::Underline => "u",
+            Tag::Link(_) => "a",
+            Tag::Other(name) => name,
+        }
+    }
+}
+
+impl Markup {
+    /// Parse `body`, falling back to showing it verbatim if it is not well-formed.
+    pub fn parse(body: &str) -> Self {
+        parse(body).unwrap_or_else(|| {
+            tracing::debug!("malformed body markup, showing it as plain text: {body:?}");
+            Self::plain(body)
+        })
+    }
+
+    pub fn plain(text: &str) -> Self {
+        let spans = if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![Span {
+                text: text.to_owned(),
+                style: Style::default(),
+            }]
+        };
+        Self { spans }
+    }
+
+    /// The text without any formatting.
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| &*span.text).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    fn push(&mut self, text: &str, tags: &[Tag]) {
+        if text.is_empty() {
+            return;
+        }
+        let mut style = Style::default();
+        for tag in tags {
+            match tag {
+                Tag::Bold => style.bold = true,
+                Tag::Italic => style.italic = true,
+                Tag::Underline => style.underline = true,
+                Tag::Link(href) => style.link = Some(href.clone()),
+                Tag::Other(_) => {}
+            }
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(text),
+            _ => self.spans.push(Span {
+                text: text.to_owned(),
+                style,
+            }),
+        }
+    }
+}
+
+fn parse(mut input: &str) -> Option<Markup> {
+    let mut markup = Markup::default();
+    let mut tags = Vec::new();
+    while !input.is_empty() {
+        let Some(start) = input.find('<') else {
+            markup.push(&unescape(input), &tags);
+            break;
+        };
+        markup.push(&unescape(&input[..start]), &tags);
+
+        let end = start + input[start..].find('>')?;
+        let tag = input[start + 1..end].trim();
+        input = &input[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            // be lenient with tags that were opened but never closed, like `<br>`
+            let index = tags.iter().rposition(|t: &Tag| t.name() == name)?;
+            tags.truncate(index);
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let (name, attrs) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        let tag = match name {
+            "b" => Tag::Bold,
+            "i" => Tag::Italic,
+            "u" => Tag::Underline,
+            "a" => attr(attrs, "href").map_or(Tag::Other("a".to_owned()), Tag::Link),
+            "img" => {
+                if let Some(alt) = attr(attrs, "alt") {
+                    markup.push(&alt, &tags);
+                }
+                continue;
+            }
+            "br" => {
+                markup.push("\n", &tags);
+                continue;
+            }
+            name => Tag::Other(name.to_owned()),
+        };
+        if !self_closing {
+            tags.push(tag);
+        }
+    }
+    Some(markup)
+}
+
+/// Find the value of the attribute `name` in `attrs`, like `href="..."`.
+fn attr(mut attrs: &str, name: &str) -> Option<String> {
+    loop {
+        let (key, rest) = attrs.split_once('=')?;
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, rest) = rest[1..].split_once(quote)?;
+        if key.trim() == name {
+            return Some(unescape(value));
+        }
+        attrs = rest;
+    }
+}
+
+/// Replace entity escapes, keeping stray `&`s as they are.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((entity(&rest[1..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_owned(),
+            style,
+        }
+    }
+
+    #[test]
+    fn nested_tags_merge_their_styles() {
+        let markup = Markup::parse("plain <b>bold <i>both</i></b> <u>under</u>");
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let both = Style {
+            italic: true,
+            ..bold.clone()
+        };
+        let underline = Style {
+            underline: true,
+            ..Style::default()
+        };
+        assert_eq!(
+            markup.spans,
+            [
+                span("plain ", Style::default()),
+                span("bold ", bold),
+                span("both", both),
+                span(" ", Style::default()),
+                span("under", underline),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_images_and_line_breaks() {
+        let markup = Markup::parse(
+            r#"<a href="https://example.com/?a=1&amp;b=2">site</a><br><img src="x.png" alt="[cat]"/>"#,
+        );
+        assert_eq!(
+            markup.spans[0],
+            span(
+                "site",
+                Style {
+                    link: Some("https://example.com/?a=1&b=2".to_owned()),
+                    ..Style::default()
+                }
+            )
+        );
+        assert_eq!(markup.text(), "site\n[cat]");
+    }
+
+    #[test]
+    fn unknown_tags_keep_their_content() {
+        assert_eq!(
+            Markup::parse("<span>kept</span> <a>no href</a>").text(),
+            "kept no href"
+        );
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            Markup::parse("&lt;3 &amp; &#65;&#x42; &nbsp;AT&T &bogus;").text(),
+            "<3 & AB \u{a0}AT&T &bogus;"
+        );
+    }
+
+    #[test]
+    fn malformed_markup_is_shown_verbatim() {
+        for body in ["a < b", "unclosed <b", "</b>stray", "<1>"] {
+            let markup = Markup::parse(body);
+            assert_eq!(markup.spans, [span(body, Style::default())], "{body}");
+        }
+    }
+
+    #[test]
+    fn empty_body() {
+        assert!(Markup::parse("").is_empty());
+        assert!(Markup::parse("<b></b>").is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use crate::config::Config;
+use crate::hints::Hints;
+use crate::markup::Markup;
+use crate::rules::Style;
+
+/// A notification as received through `org.freedesktop.Notifications.Notify`.
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// `body`, parsed.
+    pub markup: Markup,
+    pub actions: Vec<String>,
+    pub hints: Hints,
+    pub expire_timeout: i32,
+    /// Colors set by rules.
+    pub style: Style,
+    /// Kept from popping up by a rule.
+    pub hidden: bool,
+    /// Kept out of the history by a rule, regardless of the `transient` hint.
+    pub skip_history: bool,
+
+    /// When the notification was first created.
+    pub created_at: SystemTime,
+    /// When the notification was last replaced, equal to `created_at` if it never was.
+    pub updated_at: SystemTime,
+    /// When the notification should be closed with [`CloseReason::Expired`], if ever.
+    pub expires_at: Option<Instant>,
+    /// Kept from popping up by Do Not Disturb, see [`crate::dnd::Dnd`].
+    pub suppressed: bool,
+}
+
+impl Notification {
+    /// The actions of the notification as pairs of key and label.
+    pub fn actions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.actions
+            .chunks_exact(2)
+            .map(|action| (&*action[0], &*action[1]))
+    }
+
+    /// The key of the action that asks for an inline reply instead of being invoked.
+    pub const INLINE_REPLY: &'static str = "inline-reply";
+
+    /// The label of the action with the given key.
+    pub fn action(&self, key: &str) -> Option<&str> {
+        self.actions()
+            .find(|(action, _)| *action == key)
+            .map(|(_, label)| label)
+    }
+}
+
+/// The reason passed along with the `NotificationClosed` signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CloseReason {
+    /// The notification expired.
+    Expired = 1,
+    /// The notification was dismissed by the user.
+    Dismissed = 2,
+    /// The notification was closed by a call to `CloseNotification`.
+    Closed = 3,
+    /// Undefined/reserved reasons.
+    Undefined = 4,
+}
+
+/// Messages sent from the D-Bus side to the Wayland event loop.
+#[derive(Debug)]
+pub enum Message {
+    /// The notification with this id was added to the store or replaced.
+    Notify(u32),
+    /// The configuration file was changed.
+    Reload(Arc<Config>),
+    /// Notifications were closed or Do Not Disturb was switched.
+    Redraw,
+    /// Another daemon took over the bus name, so nothing should be shown
+    /// anymore.
+    Shutdown,
+}
+
+/// Signals the Wayland event loop and the servers ask to be emitted.
+#[derive(Debug)]
+pub enum Signal {
+    /// The notification with this id was added or replaced, see `rs.uwu.Notify`.
+    NotificationAdded(u32),
+    NotificationClosed(u32, CloseReason),
+    ActionInvoked(u32, String),
+    ActivationToken(u32, String),
+    NotificationReplied(u32, String),
+}
@@ -1,39 +1,27 @@
-use egui::RawInput;
-use egui_glow::ShaderVersion;
+use egui::epaint::ClippedPrimitive;
 
-use crate::wayland::State;
-
-/// Use [`egui`] from a [`glow`] app based on [`winit`].
-pub struct EguiGlow {
+/// Runs the UI, keeping what it produced until it is painted by a
+/// [`crate::render::Renderer`].
+#[derive(Default)]
+pub struct Egui {
     pub egui_ctx: egui::Context,
-    pub painter: egui_glow::Painter,
 
     shapes: Vec<egui::epaint::ClippedShape>,
     textures_delta: egui::TexturesDelta,
     pub input: egui::RawInput,
 }
 
-impl EguiGlow {
-    /// For automatic shader version detection set `shader_version` to `None`.
-    pub fn new(gl: std::sync::Arc<glow::Context>, shader_version: Option<ShaderVersion>) -> Self {
-        let painter = egui_glow::Painter::new(gl, "", shader_version)
-            .map_err(|err| {
-                tracing::error!("error occurred in initializing painter:\n{err}");
-            })
-            .unwrap();
-
-        Self {
-            egui_ctx: Default::default(),
-            painter,
-            shapes: Default::default(),
-            textures_delta: Default::default(),
-            input: RawInput::default(),
-        }
-    }
+/// What to paint, from the last call to [`Egui::run`].
+pub struct Frame {
+    pub primitives: Vec<ClippedPrimitive>,
+    pub textures_delta: egui::TexturesDelta,
+    pub pixels_per_point: f32,
+}
 
+impl Egui {
     /// Returns the `Duration` of the timeout after which egui should be repainted even if there's no new events.
     ///
-    /// Call [`Self::paint`] later to paint.
+    /// Call [`Self::frame`] later to paint.
     pub fn run(
         &mut self,
         [width, height]: [u32; 2],
@@ -45,7 +33,6 @@ impl EguiGlow {
         ));
         let raw_input = self.input.take();
         let egui::FullOutput {
-            // TODO
             platform_output,
             repaint_after,
             textures_delta,
@@ -54,32 +41,29 @@ impl EguiGlow {
 
         self.shapes = shapes;
         self.textures_delta.append(textures_delta);
+        if let Some(open_url) = platform_output.open_url {
+            open(&open_url.url);
+        }
         repaint_after
     }
 
-    /// Paint the results of the last call to [`Self::run`].
-    pub fn paint(&mut self, dimensions: [u32; 2]) {
+    /// Tessellate the results of the last call to [`Self::run`].
+    pub fn frame(&mut self) -> Frame {
         let shapes = std::mem::take(&mut self.shapes);
-        let mut textures_delta = std::mem::take(&mut self.textures_delta);
-
-        for (id, image_delta) in textures_delta.set {
-            self.painter.set_texture(id, &image_delta);
-        }
-
-        let clipped_primitives = self.egui_ctx.tessellate(shapes);
-        self.painter.paint_primitives(
-            dimensions,
-            self.egui_ctx.pixels_per_point(),
-            &clipped_primitives,
-        );
-
-        for id in textures_delta.free.drain(..) {
-            self.painter.free_texture(id);
+        Frame {
+            primitives: self.egui_ctx.tessellate(shapes),
+            textures_delta: std::mem::take(&mut self.textures_delta),
+            pixels_per_point: self.egui_ctx.pixels_per_point(),
         }
     }
+}
 
-    /// Call to release the allocated graphics resources.
-    pub fn destroy(&mut self) {
-        self.painter.destroy();
+/// Open a link clicked in a notification with the user's preferred application.
+fn open(url: &str) {
+    tracing::debug!("opening {url}");
+    match std::process::Command::new("xdg-open").arg(url).spawn() {
+        // reap the child once it is done
+        Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+        Err(err) => tracing::error!("failed to run xdg-open: {err}"),
     }
 }
@@ -0,0 +1,261 @@
+//! Painting what egui produced on the CPU, for when OpenGL does not work.
+//!
+//! Colors are multiplied and blended in gamma space with premultiplied alpha,
+//! the way `egui_glow` does it, so that both look the same.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use egui::epaint::{ClippedPrimitive, ImageData, ImageDelta, Mesh, Primitive, Vertex};
+use egui::{Color32, Pos2, TextureFilter, TextureId};
+
+/// Premultiplied pixels, row by row.
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color32>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color32::TRANSPARENT; width as usize * height as usize],
+        }
+    }
+
+    /// Resize the canvas and fill it with `color`.
+    pub fn clear(&mut self, width: u32, height: u32, color: Color32) {
+        self.width = width;
+        self.height = height;
+        self.pixels.clear();
+        self.pixels.resize(width as usize * height as usize, color);
+    }
+
+    /// The pixels as straight, not premultiplied, RGBA bytes.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| color.to_srgba_unmultiplied())
+            .collect()
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba())?;
+        writer.finish()
+    }
+}
+
+struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+    filter: TextureFilter,
+}
+
+impl Texture {
+    /// The color at `uv`, as premultiplied channels.
+    fn sample(&self, uv: Pos2) -> [f32; 4] {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.round(), y.round()),
+            TextureFilter::Linear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let top = lerp(self.texel(x0, y0), self.texel(x0 + 1.0, y0), tx);
+                let bottom = lerp(self.texel(x0, y0 + 1.0), self.texel(x0 + 1.0, y0 + 1.0), tx);
+                lerp(top, bottom, ty)
+            }
+        }
+    }
+
+    /// The texel at `x`, `y`, the edges being repeated outside of the texture.
+    fn texel(&self, x: f32, y: f32) -> [f32; 4] {
+        let x = (x.max(0.0) as usize).min(self.width - 1);
+        let y = (y.max(0.0) as usize).min(self.height - 1);
+        channels(self.pixels[y * self.width + x])
+    }
+}
+
+/// Paints tessellated egui output onto a [`Canvas`].
+#[derive(Default)]
+pub struct Painter {
+    textures: HashMap<TextureId, Texture>,
+}
+
+impl Painter {
+    pub fn set_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let ([width, height], pixels): (_, Vec<_>) = match &delta.image {
+            ImageData::Color(image) => (image.size, image.pixels.clone()),
+            ImageData::Font(image) => (image.size, image.srgba_pixels(None).collect()),
+        };
+        let Some([x, y]) = delta.pos else {
+            let texture = Texture {
+                width,
+                height,
+                pixels,
+                filter: delta.options.magnification,
+            };
+            self.textures.insert(id, texture);
+            return;
+        };
+        let Some(texture) = self.textures.get_mut(&id) else {
+            tracing::warn!("partial update of unknown texture {id:?}");
+            return;
+        };
+        for (row, pixels) in pixels.chunks_exact(width).enumerate() {
+            let start = (y + row) * texture.width + x;
+            texture.pixels[start..start + width].copy_from_slice(pixels);
+        }
+    }
+
+    pub fn free_texture(&mut self, id: TextureId) {
+        self.textures.remove(&id);
+    }
+
+    pub fn paint(
+        &self,
+        canvas: &mut Canvas,
+        pixels_per_point: f32,
+        primitives: &[ClippedPrimitive],
+    ) {
+        for primitive in primitives {
+            let Primitive::Mesh(mesh) = &primitive.primitive else {
+                tracing::warn!("paint callbacks cannot be drawn without OpenGL");
+                continue;
+            };
+            let clip = primitive.clip_rect;
+            let clip = [
+                (clip.min.x * pixels_per_point)
+                    .round()
+                    .clamp(0.0, canvas.width as f32) as usize,
+                (clip.min.y * pixels_per_point)
+                    .round()
+                    .clamp(0.0, canvas.height as f32) as usize,
+                (clip.max.x * pixels_per_point)
+                    .round()
+                    .clamp(0.0, canvas.width as f32) as usize,
+                (clip.max.y * pixels_per_point)
+                    .round()
+                    .clamp(0.0, canvas.height as f32) as usize,
+            ];
+            self.paint_mesh(canvas, clip, pixels_per_point, mesh);
+        }
+    }
+
+    fn paint_mesh(
+        &self,
+        canvas: &mut Canvas,
+        clip: [usize; 4],
+        pixels_per_point: f32,
+        mesh: &Mesh,
+    ) {
+        let Some(texture) = self.textures.get(&mesh.texture_id) else {
+            tracing::warn!("mesh uses unknown texture {:?}", mesh.texture_id);
+            return;
+        };
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            fill_triangle(canvas, clip, pixels_per_point, texture, [a, b, c]);
+        }
+    }
+}
+
+fn fill_triangle(
+    canvas: &mut Canvas,
+    [clip_left, clip_top, clip_right, clip_bottom]: [usize; 4],
+    pixels_per_point: f32,
+    texture: &Texture,
+    mut vertices: [&Vertex; 3],
+) {
+    let mut points = vertices.map(|vertex| vertex.pos.to_vec2() * pixels_per_point);
+    let mut area = edge(points[0], points[1], points[2]);
+    if area < 0.0 {
+        vertices.swap(1, 2);
+        points.swap(1, 2);
+        area = -area;
+    }
+    // degenerate, or NaN from a broken mesh
+    if area == 0.0 || area.is_nan() {
+        return;
+    }
+    let [a, b, c] = points;
+
+    let left = a.x.min(b.x).min(c.x).floor().max(clip_left as f32) as usize;
+    let top = a.y.min(b.y).min(c.y).floor().max(clip_top as f32) as usize;
+    let right = (a.x.max(b.x).max(c.x).ceil().max(0.0) as usize).min(clip_right);
+    let bottom = (a.y.max(b.y).max(c.y).ceil().max(0.0) as usize).min(clip_bottom);
+
+    let colors = vertices.map(|vertex| channels(vertex.color));
+    for y in top..bottom {
+        for x in left..right {
+            let p = egui::vec2(x as f32 + 0.5, y as f32 + 0.5);
+            let weights = [(b, c), (c, a), (a, b)].map(|(from, to)| edge(from, to, p));
+            let inside = [(b, c), (c, a), (a, b)]
+                .iter()
+                .zip(weights)
+                .all(|(&(from, to), weight)| weight > 0.0 || weight == 0.0 && owns(from, to));
+            if !inside {
+                continue;
+            }
+            let weights = weights.map(|weight| weight / area);
+            let uv = vertices
+                .iter()
+                .zip(weights)
+                .fold(Pos2::ZERO, |uv, (vertex, weight)| {
+                    uv + vertex.uv.to_vec2() * weight
+                });
+            let mut color = [0.0; 4];
+            for (vertex, weight) in colors.iter().zip(weights) {
+                for (channel, value) in color.iter_mut().zip(vertex) {
+                    *channel += value * weight;
+                }
+            }
+            let texel = texture.sample(uv);
+            let src = [0, 1, 2, 3].map(|i| color[i] * texel[i] / 255.0);
+            let dst = &mut canvas.pixels[y * canvas.width as usize + x];
+            *dst = blend(*dst, src);
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`, positive if `p` is
+/// clockwise of `a` to `b` on screen.
+fn edge(a: egui::Vec2, b: egui::Vec2, p: egui::Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Whether pixels right on the edge from `a` to `b` belong to this triangle.
+///
+/// Triangles sharing an edge go along it in opposite directions, so exactly one
+/// of them gets the pixels and translucent shapes are not blended twice there.
+fn owns(a: egui::Vec2, b: egui::Vec2) -> bool {
+    let d = b - a;
+    d.y > 0.0 || d.y == 0.0 && d.x < 0.0
+}
+
+fn channels(color: Color32) -> [f32; 4] {
+    color.to_array().map(f32::from)
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// `src` over `dst`, both premultiplied.
+fn blend(dst: Color32, [r, g, b, a]: [f32; 4]) -> Color32 {
+    let keep = 1.0 - a / 255.0;
+    let [dr, dg, db, da] = channels(dst);
+    Color32::from_rgba_premultiplied(
+        (r + dr * keep).round() as u8,
+        (g + dg * keep).round() as u8,
+        (b + db * keep).round() as u8,
+        (a + da * keep).round() as u8,
+    )
+}
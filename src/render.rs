@@ -0,0 +1,291 @@
+//! Drawing frames onto the layer surface, with OpenGL through EGL or, where
+//! that does not work, on the CPU into shared memory.
+
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, WrapErr};
+use egui::Color32;
+use glow::HasContext;
+use glutin::display::{Display, DisplayApiPreference, GetGlDisplay};
+use glutin::prelude::{
+    GlDisplay, NotCurrentGlContextSurfaceAccessor, PossiblyCurrentContextGlSurfaceAccessor,
+};
+use glutin::surface::GlSurface;
+use raw_window_handle::{
+    RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
+};
+use sctk::reexports::client::protocol::{wl_shm, wl_surface::WlSurface};
+use sctk::reexports::client::{Connection, Proxy};
+use sctk::shm::slot::{Buffer, SlotPool};
+use sctk::shm::Shm;
+use smithay_client_toolkit as sctk;
+
+use crate::paint::Frame;
+use crate::raster::{self, Canvas};
+
+type GlutinSurface = glutin::surface::Surface<glutin::surface::WindowSurface>;
+
+/// Draws frames onto one surface at a time.
+pub trait Renderer {
+    /// Start drawing onto a new surface, the previous one was detached.
+    fn attach(&mut self, surface: &WlSurface, width: u32, height: u32) -> color_eyre::Result<()>;
+
+    /// Stop drawing onto the surface, which is about to be destroyed.
+    fn detach(&mut self);
+
+    fn resize(&mut self, width: u32, height: u32);
+
+    /// Draw a frame onto `background` and attach it to the surface, which is
+    /// left for the caller to damage and commit.
+    fn paint(&mut self, surface: &WlSurface, size: [u32; 2], frame: Frame, background: Color32);
+}
+
+/// Draws with OpenGL, or OpenGL ES if that is all there is.
+pub struct Gl {
+    config: glutin::config::Config,
+    context: glutin::context::PossiblyCurrentContext,
+    gl: Arc<glow::Context>,
+    painter: egui_glow::Painter,
+    surface: Option<GlutinSurface>,
+}
+
+impl Gl {
+    /// Set up EGL, drawing onto `surface` right away as the painter needs a
+    /// current context to be created.
+    pub fn new(
+        conn: &Connection,
+        surface: &WlSurface,
+        width: u32,
+        height: u32,
+    ) -> color_eyre::Result<Self> {
+        let mut wl = WaylandDisplayHandle::empty();
+        wl.display = conn.display().id().as_ptr().cast();
+        let display =
+            unsafe { Display::new(RawDisplayHandle::Wayland(wl), DisplayApiPreference::Egl)? };
+
+        let config_template_builder = glutin::config::ConfigTemplateBuilder::new()
+            .prefer_hardware_accelerated(None)
+            .with_depth_size(0)
+            .with_stencil_size(0)
+            .with_transparency(true);
+
+        let mut configs = unsafe { display.find_configs(config_template_builder.build())? };
+        let config = configs
+            .next()
+            .ok_or_else(|| eyre!("no EGL config with transparency"))?;
+        let gl_display = config.display();
+        // the context outlives the surfaces, which come and go with the notifications
+        let context_attributes = glutin::context::ContextAttributesBuilder::new().build(None);
+        // by default, glutin will try to create a core opengl context. but, if it is not available, try to create a gl-es context using this fallback attributes
+        let fallback_context_attributes = glutin::context::ContextAttributesBuilder::new()
+            .with_context_api(glutin::context::ContextApi::Gles(None))
+            .build(None);
+        let not_current_context = unsafe {
+            gl_display
+                .create_context(&config, &context_attributes)
+                .or_else(|err| {
+                    tracing::debug!("failed to create gl_context with attributes: {:?}: {err}. retrying with fallback context attributes: {:?}",
+                        &context_attributes,
+                        &fallback_context_attributes);
+                    gl_display.create_context(&config, &fallback_context_attributes)
+                })
+                .wrap_err("failed to create context even with fallback attributes")?
+        };
+
+        let gl_surface = window_surface(&config, surface, width, height)?;
+        tracing::debug!("surface created successfully: {gl_surface:?}.making context current");
+        let context = not_current_context.make_current(&gl_surface)?;
+        pace(&gl_surface, &context)?;
+
+        let gl =
+            unsafe { glow::Context::from_loader_function_cstr(|x| gl_display.get_proc_address(x)) };
+        let gl = Arc::new(gl);
+        let painter = egui_glow::Painter::new(gl.clone(), "", None)
+            .map_err(|err| eyre!("failed to set up the painter: {err}"))?;
+
+        Ok(Self {
+            config,
+            context,
+            gl,
+            painter,
+            surface: Some(gl_surface),
+        })
+    }
+}
+
+impl Renderer for Gl {
+    fn attach(&mut self, surface: &WlSurface, width: u32, height: u32) -> color_eyre::Result<()> {
+        let gl_surface = window_surface(&self.config, surface, width, height)?;
+        self.context.make_current(&gl_surface)?;
+        pace(&gl_surface, &self.context)?;
+        self.surface = Some(gl_surface);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.surface = None;
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Some(surface) = &self.surface {
+            surface.resize(
+                &self.context,
+                NonZeroU32::new(width.max(1)).unwrap(),
+                NonZeroU32::new(height.max(1)).unwrap(),
+            );
+        }
+    }
+
+    fn paint(&mut self, _: &WlSurface, size: [u32; 2], frame: Frame, background: Color32) {
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        let Frame {
+            primitives,
+            mut textures_delta,
+            pixels_per_point,
+        } = frame;
+        for (id, image_delta) in textures_delta.set {
+            self.painter.set_texture(id, &image_delta);
+        }
+
+        let [r, g, b, a] = background.to_normalized_gamma_f32();
+        unsafe {
+            self.gl.clear_color(r, g, b, a);
+            self.gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+        self.painter
+            .paint_primitives(size, pixels_per_point, &primitives);
+
+        for id in textures_delta.free.drain(..) {
+            self.painter.free_texture(id);
+        }
+        if let Err(err) = surface.swap_buffers(&self.context) {
+            tracing::error!("failed to swap buffers: {err}");
+        }
+    }
+}
+
+fn window_surface(
+    config: &glutin::config::Config,
+    surface: &WlSurface,
+    width: u32,
+    height: u32,
+) -> color_eyre::Result<GlutinSurface> {
+    let mut window_handle = WaylandWindowHandle::empty();
+    window_handle.surface = surface.id().as_ptr().cast();
+    let surface_attributes =
+        glutin::surface::SurfaceAttributesBuilder::<glutin::surface::WindowSurface>::new().build(
+            RawWindowHandle::Wayland(window_handle),
+            NonZeroU32::new(width.max(1)).unwrap(),
+            NonZeroU32::new(height.max(1)).unwrap(),
+        );
+    tracing::debug!("creating surface with attributes: {surface_attributes:?}");
+    Ok(unsafe {
+        config
+            .display()
+            .create_window_surface(config, &surface_attributes)?
+    })
+}
+
+/// Leave the pacing to frame callbacks, blocking on swapping buffers would
+/// block D-Bus too.
+fn pace(
+    surface: &GlutinSurface,
+    context: &glutin::context::PossiblyCurrentContext,
+) -> glutin::error::Result<()> {
+    surface.set_swap_interval(context, glutin::surface::SwapInterval::DontWait)
+}
+
+/// Draws on the CPU into buffers shared with the compositor.
+pub struct Software {
+    pool: SlotPool,
+    buffer: Option<Buffer>,
+    painter: raster::Painter,
+    canvas: Canvas,
+}
+
+impl Software {
+    pub fn new(shm: &Shm) -> color_eyre::Result<Self> {
+        // grows as needed once the size of the surface is known
+        let pool = SlotPool::new(1, shm).wrap_err("failed to create a shared memory pool")?;
+        Ok(Self {
+            pool,
+            buffer: None,
+            painter: raster::Painter::default(),
+            canvas: Canvas::new(0, 0),
+        })
+    }
+}
+
+impl Renderer for Software {
+    fn attach(&mut self, _: &WlSurface, _: u32, _: u32) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        self.buffer = None;
+    }
+
+    fn resize(&mut self, _: u32, _: u32) {
+        // buffers are sized when painting
+    }
+
+    fn paint(
+        &mut self,
+        surface: &WlSurface,
+        [width, height]: [u32; 2],
+        frame: Frame,
+        background: Color32,
+    ) {
+        let Frame {
+            primitives,
+            textures_delta,
+            pixels_per_point,
+        } = frame;
+        for (id, image_delta) in &textures_delta.set {
+            self.painter.set_texture(*id, image_delta);
+        }
+        self.canvas.clear(width, height, background);
+        self.painter
+            .paint(&mut self.canvas, pixels_per_point, &primitives);
+        for id in textures_delta.free {
+            self.painter.free_texture(id);
+        }
+
+        let stride = width as i32 * 4;
+        // the compositor may still be reading the last buffer, in which case
+        // a new one is needed to draw into
+        let reusable = self.buffer.as_ref().is_some_and(|buffer| {
+            buffer.height() == height as i32
+                && buffer.stride() == stride
+                && self.pool.canvas(buffer).is_some()
+        });
+        if !reusable {
+            let created = self.pool.create_buffer(
+                width as i32,
+                height as i32,
+                stride,
+                wl_shm::Format::Argb8888,
+            );
+            match created {
+                Ok((buffer, _)) => self.buffer = Some(buffer),
+                Err(err) => {
+                    tracing::error!("failed to create a buffer: {err}");
+                    return;
+                }
+            }
+        }
+        let buffer = self.buffer.as_ref().unwrap();
+        let canvas = self.pool.canvas(buffer).unwrap();
+        // premultiplied little-endian ARGB
+        for (pixel, color) in canvas.chunks_exact_mut(4).zip(&self.canvas.pixels) {
+            let [r, g, b, a] = color.to_array();
+            pixel.copy_from_slice(&[b, g, r, a]);
+        }
+        if let Err(err) = buffer.attach_to(surface) {
+            tracing::error!("failed to attach the buffer: {err}");
+        }
+    }
+}
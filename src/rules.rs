@@ -0,0 +1,343 @@
+//! `[[rule]]`s from the configuration, changing notifications as they come in.
+//!
+//! ```toml
+//! [[rule]]
+//! name = "quiet music"
+//! match = { app-name = "^(Spotify|mpv)$", urgency = "low" }
+//! timeout = 2
+//! history = false
+//! ```
+//!
+//! Every rule whose conditions all hold is applied, in the order they are
+//! written, so later rules win.
+
+use std::collections::HashMap;
+use std::process::Command;
+
+use regex::Regex;
+use serde::Deserialize;
+use zbus::zvariant::OwnedValue;
+
+use crate::config::{Color, Seconds};
+use crate::hints::{self, Urgency};
+use crate::notification::Notification;
+use crate::store::NewNotification;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rule {
+    /// Shown in the log when the rule matches.
+    pub name: Option<String>,
+    #[serde(rename = "match", default)]
+    pub conditions: Conditions,
+
+    /// Replaces the `expire_timeout` asked for, `0` meaning never.
+    pub timeout: Option<Seconds>,
+    pub urgency: Option<Urgency>,
+    #[serde(default)]
+    pub style: Style,
+    /// Keep the notification from popping up at all.
+    pub suppress: Option<bool>,
+    /// Set to `false` to keep the notification out of the history.
+    pub history: Option<bool>,
+    pub resident: Option<bool>,
+    /// A command to run, with the notification in `UWU_*` environment variables.
+    pub exec: Option<Hook>,
+}
+
+/// What a notification has to look like for a rule to apply.
+///
+/// Text is matched with regular expressions, which match anywhere unless
+/// anchored with `^` and `$`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Conditions {
+    pub app_name: Option<Pattern>,
+    pub summary: Option<Pattern>,
+    pub body: Option<Pattern>,
+    pub category: Option<Pattern>,
+    pub desktop_entry: Option<Pattern>,
+    pub urgency: Option<Urgency>,
+    /// Hints that have to be present, with patterns for their values.
+    /// Numbers and booleans are matched in their decimal or `true`/`false` form.
+    pub hints: HashMap<String, Pattern>,
+}
+
+/// Colors overriding the configured ones for a single notification.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub background: Option<Color>,
+    pub text: Option<Color>,
+    pub border: Option<Color>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Pattern {
+    fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+/// A program and its arguments.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for Hook {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut argv = Vec::<String>::deserialize(deserializer)?.into_iter();
+        let program = argv.next().ok_or_else(|| {
+            serde::de::Error::custom("expected a program to run, followed by its arguments")
+        })?;
+        Ok(Self {
+            program,
+            args: argv.collect(),
+        })
+    }
+}
+
+impl Rule {
+    /// The name of the rule for logging, or its position among the rules.
+    pub fn describe(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("{name:?}"),
+            None => format!("#{}", index + 1),
+        }
+    }
+
+    /// Check the conditions on hints, which have to be looked at before the
+    /// standard ones are taken out by [`hints::Hints::parse`].
+    pub fn matches_hints(&self, hints: &HashMap<String, OwnedValue>) -> bool {
+        self.conditions.hints.iter().all(|(key, pattern)| {
+            hints
+                .get(key)
+                .and_then(|value| hints::text(value))
+                .is_some_and(|text| pattern.is_match(&text))
+        })
+    }
+
+    /// Check the remaining conditions, see [`Rule::matches_hints`].
+    pub fn matches(&self, new: &NewNotification) -> bool {
+        let conditions = &self.conditions;
+        let text = |pattern: &Option<Pattern>, text: Option<&str>| match pattern {
+            Some(pattern) => text.is_some_and(|text| pattern.is_match(text)),
+            None => true,
+        };
+        text(&conditions.app_name, Some(&new.app_name))
+            && text(&conditions.summary, Some(&new.summary))
+            && text(&conditions.body, Some(&new.body))
+            && text(&conditions.category, new.hints.category.as_deref())
+            && text(
+                &conditions.desktop_entry,
+                new.hints.desktop_entry.as_deref(),
+            )
+            && conditions
+                .urgency
+                .is_none_or(|urgency| urgency == new.hints.urgency)
+    }
+
+    pub fn apply(&self, new: &mut NewNotification) {
+        if let Some(timeout) = self.timeout {
+            new.expire_timeout = timeout.0.map_or(0, |timeout| {
+                timeout.as_millis().try_into().unwrap_or(i32::MAX)
+            });
+        }
+        if let Some(urgency) = self.urgency {
+            new.hints.urgency = urgency;
+        }
+        let style = &mut new.style;
+        style.background = self.style.background.or(style.background);
+        style.text = self.style.text.or(style.text);
+        style.border = self.style.border.or(style.border);
+        if let Some(suppress) = self.suppress {
+            new.hidden = suppress;
+        }
+        if let Some(history) = self.history {
+            new.skip_history = !history;
+        }
+        if let Some(resident) = self.resident {
+            new.hints.resident = resident;
+        }
+    }
+}
+
+impl Hook {
+    /// The command to run for a notification, see [`spawn`].
+    pub fn command(&self, notification: &Notification) -> Command {
+        let urgency = match notification.hints.urgency {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        };
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .env("UWU_ID", notification.id.to_string())
+            .env("UWU_APP_NAME", &notification.app_name)
+            .env("UWU_APP_ICON", &notification.app_icon)
+            .env("UWU_SUMMARY", &notification.summary)
+            .env("UWU_BODY", &notification.body)
+            .env("UWU_URGENCY", urgency)
+            .env(
+                "UWU_CATEGORY",
+                notification.hints.category.as_deref().unwrap_or(""),
+            );
+        command
+    }
+}
+
+/// Run the command of a hook, without waiting for it.
+pub fn spawn(mut command: Command) {
+    match command.spawn() {
+        // reap the child once it is done
+        Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+        Err(err) => tracing::error!("failed to run {:?}: {err}", command.get_program()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant::Value;
+
+    use super::*;
+    use crate::hints::Hints;
+
+    fn rule(toml: &str) -> Rule {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn raw_hints(hints: &[(&str, Value<'_>)]) -> HashMap<String, OwnedValue> {
+        hints
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone().into()))
+            .collect()
+    }
+
+    fn new(app_name: &str, summary: &str, hints: &[(&str, Value<'_>)]) -> NewNotification {
+        NewNotification {
+            app_name: app_name.to_owned(),
+            app_icon: String::new(),
+            summary: summary.to_owned(),
+            body: "body".to_owned(),
+            actions: Vec::new(),
+            hints: Hints::parse(raw_hints(hints)),
+            expire_timeout: -1,
+            style: Style::default(),
+            hidden: false,
+            skip_history: false,
+        }
+    }
+
+    #[test]
+    fn every_condition_has_to_hold() {
+        let rule = rule(
+            r#"match = { app-name = "^(Spotify|mpv)$", summary = "Now playing", urgency = "low" }"#,
+        );
+        let low = [("urgency", Value::U8(0))];
+        assert!(rule.matches(&new("mpv", "Now playing: song", &low)));
+        assert!(!rule.matches(&new("mpv2", "Now playing: song", &low)));
+        assert!(!rule.matches(&new("mpv", "Paused", &low)));
+        assert!(!rule.matches(&new("mpv", "Now playing: song", &[])));
+    }
+
+    #[test]
+    fn missing_hints_do_not_match() {
+        let rule = rule(r#"match = { category = "^email", desktop-entry = "Mail" }"#);
+        let hints = [
+            ("category", Value::from("email.arrived")),
+            ("desktop-entry", Value::from("org.example.Mail")),
+        ];
+        assert!(rule.matches(&new("mail", "Alice", &hints)));
+        assert!(!rule.matches(&new("mail", "Alice", &hints[..1])));
+        assert!(!rule.matches(&new("mail", "Alice", &[])));
+    }
+
+    #[test]
+    fn no_conditions_match_everything() {
+        let rule = rule("suppress = true");
+        assert!(rule.matches_hints(&HashMap::new()));
+        assert!(rule.matches(&new("anything", "at all", &[])));
+    }
+
+    #[test]
+    fn hint_conditions() {
+        let rule = rule(
+            r#"
+            [match.hints]
+            x-canonical-private-synchronous = "^volume$"
+            value = "^100$"
+            suppress-sound = "true"
+            "#,
+        );
+        let hints = |value| {
+            raw_hints(&[
+                ("x-canonical-private-synchronous", Value::from("volume")),
+                ("value", value),
+                ("suppress-sound", Value::Bool(true)),
+            ])
+        };
+        assert!(rule.matches_hints(&hints(Value::I32(100))));
+        assert!(rule.matches_hints(&hints(Value::new(Value::U8(100)))));
+        assert!(!rule.matches_hints(&hints(Value::I32(50))));
+        let mut missing = hints(Value::I32(100));
+        missing.remove("suppress-sound");
+        assert!(!rule.matches_hints(&missing));
+    }
+
+    #[test]
+    fn apply() {
+        let rule = rule(
+            r##"
+            timeout = 2.5
+            urgency = "critical"
+            style = { border = "#ff0000" }
+            suppress = true
+            history = false
+            resident = true
+            "##,
+        );
+        let mut new = new("app", "summary", &[]);
+        new.style.text = Some(Color(egui::Color32::WHITE));
+        rule.apply(&mut new);
+        assert_eq!(new.expire_timeout, 2500);
+        assert_eq!(new.hints.urgency, Urgency::Critical);
+        assert_eq!(new.style.border.unwrap().0, egui::Color32::RED);
+        // left alone where the rule does not say
+        assert_eq!(new.style.text.unwrap().0, egui::Color32::WHITE);
+        assert!(new.hidden);
+        assert!(new.skip_history);
+        // the client's hint is left alone
+        assert!(!new.hints.transient);
+        assert!(new.hints.resident);
+    }
+
+    #[test]
+    fn later_rules_win() {
+        let mut new = new("app", "summary", &[]);
+        let rules = [
+            rule("timeout = 10\nsuppress = true\nhistory = false"),
+            rule("timeout = 0\nhistory = true"),
+        ];
+        for rule in rules {
+            rule.apply(&mut new);
+        }
+        // never expiring
+        assert_eq!(new.expire_timeout, 0);
+        assert!(new.hidden);
+        assert!(!new.skip_history);
+    }
+}
@@ -0,0 +1,345 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::dnd::Dnd;
+use crate::hints::{Hints, Urgency};
+use crate::markup::Markup;
+use crate::notification::{CloseReason, Notification, Signal};
+use crate::rules::{Rule, Style};
+
+pub type SharedStore = Arc<Mutex<Store>>;
+
+/// The arguments of a `Notify` call, minus `replaces_id`.
+pub struct NewNotification {
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub actions: Vec<String>,
+    pub hints: Hints,
+    pub expire_timeout: i32,
+    /// Set by rules, see [`Rule::apply`].
+    pub style: Style,
+    /// Set by rules, see [`Rule::apply`].
+    pub hidden: bool,
+    /// Set by rules, see [`Rule::apply`].
+    pub skip_history: bool,
+}
+
+/// How long notifications stay around when the client lets the server decide,
+/// i.e. passes `-1` as `expire_timeout`. `None` means never expire.
+#[derive(Debug)]
+pub struct Timeouts {
+    pub low: Option<Duration>,
+    pub normal: Option<Duration>,
+    pub critical: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            low: Some(Duration::from_secs(5)),
+            normal: Some(Duration::from_secs(10)),
+            // critical notifications should not expire automatically, as per the spec
+            critical: None,
+        }
+    }
+}
+
+impl Timeouts {
+    /// Resolve the `expire_timeout` argument of `Notify` into a duration.
+    pub fn resolve(&self, expire_timeout: i32, urgency: Urgency) -> Option<Duration> {
+        match expire_timeout {
+            0 => None,
+            ms if ms > 0 => Some(Duration::from_millis(ms as u64)),
+            _ => match urgency {
+                Urgency::Low => self.low,
+                Urgency::Normal => self.normal,
+                Urgency::Critical => self.critical,
+            },
+        }
+    }
+
+    /// When a notification that was just shown or replaced should expire.
+    ///
+    /// Suppressed notifications are not shown, so they do not expire until
+    /// they are revealed.
+    pub fn expires_at(&self, notification: &Notification) -> Option<Instant> {
+        if notification.suppressed {
+            return None;
+        }
+        self.resolve(notification.expire_timeout, notification.hints.urgency)
+            .map(|timeout| Instant::now() + timeout)
+    }
+}
+
+/// All live notifications, in the order they were first shown.
+#[derive(Default)]
+pub struct Store {
+    notifications: Vec<Notification>,
+    last_id: u32,
+    pub timeouts: Timeouts,
+    pub dnd: Dnd,
+    pub rules: Vec<Rule>,
+}
+
+impl Store {
+    pub fn shared() -> SharedStore {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    /// Insert a notification, or update the one identified by `replaces_id` in place.
+    ///
+    /// A `replaces_id` that does not refer to a live notification is treated
+    /// as if it were zero, so a fresh id is allocated, unless the notification
+    /// has a synchronous tag, in which case it replaces the live notification
+    /// with the same tag. Returns the id.
+    pub fn insert(&mut self, replaces_id: u32, new: NewNotification) -> u32 {
+        let now = SystemTime::now();
+
+        let replaces_id = match &new.hints.synchronous {
+            Some(tag) if self.get(replaces_id).is_none() => self
+                .notifications
+                .iter()
+                .find(|n| n.hints.synchronous.as_ref() == Some(tag))
+                .map_or(replaces_id, |n| n.id),
+            _ => replaces_id,
+        };
+
+        if let Some(existing) = self.notifications.iter_mut().find(|n| n.id == replaces_id) {
+            tracing::debug!(
+                id = replaces_id,
+                age = ?now.duration_since(existing.created_at).unwrap_or_default(),
+                "replacing notification in place"
+            );
+            existing.app_name = new.app_name;
+            existing.app_icon = new.app_icon;
+            existing.summary = new.summary;
+            existing.markup = Markup::parse(&new.body);
+            existing.body = new.body;
+            existing.actions = new.actions;
+            existing.hints = new.hints;
+            existing.expire_timeout = new.expire_timeout;
+            existing.style = new.style;
+            existing.hidden = new.hidden;
+            existing.skip_history = new.skip_history;
+            existing.updated_at = now;
+            existing.suppressed = self.dnd.suppresses(existing);
+            existing.expires_at = self.timeouts.expires_at(existing);
+            return replaces_id;
+        }
+
+        let id = self.next_id();
+        let mut notification = Notification {
+            id,
+            app_name: new.app_name,
+            app_icon: new.app_icon,
+            summary: new.summary,
+            markup: Markup::parse(&new.body),
+            body: new.body,
+            actions: new.actions,
+            hints: new.hints,
+            expire_timeout: new.expire_timeout,
+            style: new.style,
+            hidden: new.hidden,
+            skip_history: new.skip_history,
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            suppressed: false,
+        };
+        notification.suppressed = self.dnd.suppresses(&notification);
+        notification.expires_at = self.timeouts.expires_at(&notification);
+        self.notifications.push(notification);
+        id
+    }
+
+    /// Allocate an id that is neither zero nor held by a live notification.
+    fn next_id(&mut self) -> u32 {
+        loop {
+            self.last_id = self.last_id.wrapping_add(1);
+            if self.last_id != 0 && self.get(self.last_id).is_none() {
+                return self.last_id;
+            }
+        }
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Notification> {
+        self.notifications.iter().find(|n| n.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Notification> {
+        self.notifications.iter_mut().find(|n| n.id == id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Notification> {
+        let index = self.notifications.iter().position(|n| n.id == id)?;
+        Some(self.notifications.remove(index))
+    }
+
+    /// Remove every notification whose expiry is due, returning their ids.
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<u32> {
+        let mut expired = Vec::new();
+        self.notifications.retain(|n| {
+            let due = n.expires_at.is_some_and(|at| at <= now);
+            if due {
+                expired.push(n.id);
+            }
+            !due
+        });
+        expired
+    }
+
+    /// When the next notification expires, if any does.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.notifications.iter().filter_map(|n| n.expires_at).min()
+    }
+
+    /// Push the expiry of a notification back, e.g. while it is being hovered.
+    pub fn postpone(&mut self, id: u32, by: Duration) {
+        if let Some(at) = self.get_mut(id).and_then(|n| n.expires_at.as_mut()) {
+            *at += by;
+        }
+    }
+
+    /// The ids of the notifications Do Not Disturb kept from popping up.
+    pub fn suppressed(&self) -> Vec<u32> {
+        self.notifications
+            .iter()
+            .filter(|n| n.suppressed)
+            .map(|n| n.id)
+            .collect()
+    }
+
+    /// Show the notifications Do Not Disturb kept from popping up.
+    pub fn reveal_suppressed(&mut self) {
+        for notification in &mut self.notifications {
+            if notification.suppressed {
+                notification.suppressed = false;
+                notification.expires_at = self.timeouts.expires_at(notification);
+            }
+        }
+    }
+
+    /// Remove every live notification, returning their ids.
+    pub fn clear(&mut self) -> Vec<u32> {
+        self.notifications.drain(..).map(|n| n.id).collect()
+    }
+
+    /// Iterate over live notifications, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Notification> {
+        self.notifications.iter()
+    }
+
+    /// Iterate over the notifications that pop up, oldest first.
+    pub fn visible(&self) -> impl DoubleEndedIterator<Item = &Notification> {
+        self.iter().filter(|n| !n.suppressed && !n.hidden)
+    }
+
+    /// How many notifications Do Not Disturb kept back and should be
+    /// mentioned once it is over.
+    pub fn kept_back(&self) -> usize {
+        if self.dnd.enabled {
+            0
+        } else {
+            self.notifications.iter().filter(|n| n.suppressed).count()
+        }
+    }
+
+    /// Whether there is anything to draw at all.
+    pub fn showing(&self) -> bool {
+        self.visible().next().is_some() || self.kept_back() > 0
+    }
+}
+
+/// The store along with the signals reporting what happens to its
+/// notifications, so that every frontend closes them the same way.
+pub struct Notifications {
+    pub store: SharedStore,
+    signals: UnboundedSender<Signal>,
+    /// When [`Notifications::expire`] last ran.
+    last_tick: Instant,
+}
+
+impl Notifications {
+    pub fn new(store: SharedStore, signals: UnboundedSender<Signal>) -> Self {
+        Self {
+            store,
+            signals,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Close expired notifications, not counting the time `hovered` spent
+    /// hovered since the last call. Returns whether any were closed.
+    pub fn expire(&mut self, hovered: Option<u32>) -> bool {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+
+        let mut store = self.store.lock().unwrap();
+        if let Some(id) = hovered {
+            store.postpone(id, elapsed);
+        }
+        let expired = store.remove_expired(now);
+        drop(store);
+
+        for &id in &expired {
+            tracing::debug!(id, "notification expired");
+            self.signal(Signal::NotificationClosed(id, CloseReason::Expired));
+        }
+        !expired.is_empty()
+    }
+
+    /// Remove a notification from the store and emit `NotificationClosed`
+    /// for it. Returns whether it was still there.
+    pub fn close(&self, id: u32, reason: CloseReason) -> bool {
+        let closed = self.store.lock().unwrap().remove(id).is_some();
+        if closed {
+            self.signal(Signal::NotificationClosed(id, reason));
+        }
+        closed
+    }
+
+    /// Close every live notification with the given reason. Returns whether
+    /// there were any.
+    pub fn close_all(&self, reason: CloseReason) -> bool {
+        let ids = self.store.lock().unwrap().clear();
+        for &id in &ids {
+            self.signal(Signal::NotificationClosed(id, reason));
+        }
+        !ids.is_empty()
+    }
+
+    /// Emit `ActionInvoked`, closing the notification unless it was resident.
+    /// Returns whether it was closed.
+    ///
+    /// `resident` is taken from when the action was clicked, by now the
+    /// notification might have been closed or replaced, which does not make
+    /// the click any less real.
+    pub fn invoke(&self, id: u32, key: String, resident: bool) -> bool {
+        tracing::debug!(id, key, "action invoked");
+        self.signal(Signal::ActionInvoked(id, key));
+        !resident && self.close(id, CloseReason::Dismissed)
+    }
+
+    /// Emit `NotificationReplied`, closing the notification unless it is
+    /// resident. Returns whether it was closed.
+    pub fn reply(&self, id: u32, text: String) -> bool {
+        let resident = match self.store.lock().unwrap().get(id) {
+            Some(notification) => notification.hints.resident,
+            None => return false,
+        };
+        tracing::debug!(id, "inline reply sent");
+        self.signal(Signal::NotificationReplied(id, text));
+        !resident && self.close(id, CloseReason::Dismissed)
+    }
+
+    pub fn signal(&self, signal: Signal) {
+        if self.signals.send(signal).is_err() {
+            tracing::error!("D-Bus connection is gone, cannot emit signal");
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::SystemTime;
+
+use egui::text::{LayoutJob, TextFormat};
+use egui::{
+    output::OpenUrl, Align, CursorIcon, FontSelection, Frame, Id, ImageButton, Key, Layout,
+    ProgressBar, RichText, Rounding, Sense, Stroke, TextEdit, TextureHandle, Ui, Vec2,
+};
+
+use crate::config::{Config, Font};
+use crate::hints::Urgency;
+use crate::icon::IconLoader;
+use crate::markup::Markup;
+use crate::notification::Notification;
+use crate::store::Store;
+
+/// Size of the image shown next to the summary, in logical pixels.
+const IMAGE_SIZE: f32 = 48.0;
+
+/// How long the progress bar takes to catch up with a new `value`, in seconds.
+const PROGRESS_ANIMATION_TIME: f32 = 0.2;
+
+/// Size of the icons shown on action buttons with `action-icons`, in logical pixels.
+const ACTION_ICON_SIZE: f32 = 20.0;
+
+/// Textures uploaded for notification images, keyed by notification id.
+///
+/// Textures are freed once their handle is dropped, see [`Images::forget`].
+pub struct Images {
+    textures: HashMap<u32, Option<TextureHandle>>,
+    action_icons: HashMap<(u32, String), Option<TextureHandle>>,
+    icons: IconLoader,
+}
+
+impl Images {
+    pub fn new(icons: IconLoader) -> Self {
+        Self {
+            textures: HashMap::new(),
+            action_icons: HashMap::new(),
+            icons,
+        }
+    }
+
+    /// The image of a notification: `image-data`, `image-path` or `app_icon`,
+    /// in that order, as the spec suggests.
+    fn get(&mut self, ctx: &egui::Context, notification: &Notification) -> Option<&TextureHandle> {
+        let icons = &mut self.icons;
+        self.textures
+            .entry(notification.id)
+            .or_insert_with(|| {
+                let scale = scale(ctx);
+                let hints = &notification.hints;
+                let image = hints.image_data.clone().or_else(|| {
+                    [hints.image_path.as_deref(), Some(&*notification.app_icon)]
+                        .into_iter()
+                        .flatten()
+                        .find_map(|icon| icons.load(icon, IMAGE_SIZE as u32, scale))
+                })?;
+                let name = format!("notification-{}", notification.id);
+                Some(ctx.load_texture(name, image, Default::default()))
+            })
+            .as_ref()
+    }
+
+    /// The icon named by the key of an action, for notifications with `action-icons`.
+    fn action_icon(&mut self, ctx: &egui::Context, id: u32, key: &str) -> Option<&TextureHandle> {
+        let icons = &mut self.icons;
+        self.action_icons
+            .entry((id, key.to_owned()))
+            .or_insert_with(|| {
+                let image = icons.load(key, ACTION_ICON_SIZE as u32, scale(ctx))?;
+                let name = format!("notification-{id}-action-{key}");
+                Some(ctx.load_texture(name, image, Default::default()))
+            })
+            .as_ref()
+    }
+
+    /// Free the textures of a notification that was closed or replaced.
+    pub fn forget(&mut self, id: u32) {
+        self.textures.remove(&id);
+        self.action_icons
+            .retain(|(action_id, _), _| *action_id != id);
+    }
+
+    /// Free every texture, as the surface moved to an output of another
+    /// scale, and the icons loaded for the previous one.
+    pub fn rescale(&mut self, scale: u32) {
+        self.textures.clear();
+        self.action_icons.clear();
+        self.icons.retain_scale(scale);
+    }
+
+    /// Free the textures of every notification not matching `live`.
+    pub fn retain(&mut self, mut live: impl FnMut(u32) -> bool) {
+        self.textures.retain(|&id, _| live(id));
+        self.action_icons.retain(|&(id, _), _| live(id));
+    }
+}
+
+/// The scale to load icons at, icon themes only having whole ones.
+fn scale(ctx: &egui::Context) -> u32 {
+    ctx.pixels_per_point().ceil().max(1.0) as u32
+}
+
+/// Text typed into inline reply fields but not sent yet, keyed by notification id.
+#[derive(Default)]
+pub struct Drafts {
+    drafts: HashMap<u32, String>,
+}
+
+impl Drafts {
+    /// Drop the drafts of every notification not matching `live`.
+    pub fn retain(&mut self, mut live: impl FnMut(u32) -> bool) {
+        self.drafts.retain(|&id, _| live(id));
+    }
+}
+
+/// What happened while showing the notification stack.
+#[derive(Default)]
+pub struct Output {
+    /// Height of the stack in logical pixels.
+    pub height: f32,
+    /// The notification under the pointer.
+    pub hovered: Option<u32>,
+    /// Notifications the user clicked away.
+    pub dismissed: Vec<u32>,
+    /// Actions the user invoked, as notification id and action key.
+    pub invoked: Vec<(u32, String)>,
+    /// Inline replies the user sent, as notification id and text.
+    pub replied: Vec<(u32, String)>,
+    /// Whether an inline reply field has keyboard focus.
+    pub wants_keyboard: bool,
+    /// The user asked to see the notifications Do Not Disturb kept back.
+    pub revealed: bool,
+    /// The user dismissed the notifications Do Not Disturb kept back.
+    pub dismissed_suppressed: bool,
+}
+
+impl Output {
+    /// Height of the surface to fit the stack, in logical pixels.
+    pub fn surface_height(&self, config: &Config) -> u32 {
+        let height = (self.height.ceil() as u32).max(1);
+        match config.max_height {
+            Some(max) => height.min(max.get()),
+            None => height,
+        }
+    }
+}
+
+/// What happened while showing a single card.
+struct Card {
+    response: egui::Response,
+    /// The link under the pointer.
+    link: Option<String>,
+    /// The key of the action button that was clicked.
+    action: Option<String>,
+    /// The inline reply that was sent.
+    reply: Option<String>,
+    /// Whether the pointer or keyboard is busy with the inline reply field.
+    editing: bool,
+}
+
+/// Apply the fonts and colors from the configuration.
+pub fn apply_config(ctx: &egui::Context, config: &Config) {
+    ctx.set_fonts(fonts(&config.font));
+
+    let mut style = egui::Style::default();
+    if let Some(size) = config.font.size {
+        // scale every text style along with the body text
+        let default_size = style.text_styles[&egui::TextStyle::Body].size;
+        for font_id in style.text_styles.values_mut() {
+            font_id.size *= size.0 / default_size;
+        }
+    }
+    let colors = &config.colors;
+    let visuals = &mut style.visuals;
+    if let Some(color) = colors.background {
+        visuals.window_fill = color.0;
+    }
+    if let Some(color) = colors.text {
+        visuals.override_text_color = Some(color.0);
+    }
+    if let Some(color) = colors.border {
+        visuals.window_stroke.color = color.0;
+        visuals.widgets.noninteractive.bg_stroke.color = color.0;
+    }
+    if let Some(color) = colors.critical {
+        visuals.error_fg_color = color.0;
+    }
+    if let Some(color) = colors.link {
+        visuals.hyperlink_color = color.0;
+    }
+    ctx.set_style(style);
+}
+
+/// The built-in fonts, preceded by the configured one if it can be loaded.
+fn fonts(font: &Font) -> egui::FontDefinitions {
+    let mut fonts = egui::FontDefinitions::default();
+    let Some(path) = &font.path else {
+        return fonts;
+    };
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            tracing::error!("failed to read font {}: {err}", path.display());
+            return fonts;
+        }
+    };
+    // egui panics on fonts it cannot parse, so check first
+    if let Err(err) = ab_glyph::FontRef::try_from_slice(&data) {
+        tracing::error!("failed to load font {}: {err}", path.display());
+        return fonts;
+    }
+    let name = "config".to_owned();
+    fonts
+        .font_data
+        .insert(name.clone(), egui::FontData::from_owned(data));
+    fonts
+        .families
+        .entry(egui::FontFamily::Proportional)
+        .or_default()
+        .insert(0, name);
+    fonts
+}
+
+/// Lay out the notifications of `store` that pop up, newest on top.
+pub fn show_store(
+    ctx: &egui::Context,
+    store: &Store,
+    config: &Config,
+    images: &mut Images,
+    drafts: &mut Drafts,
+) -> Output {
+    let max_visible = config.max_visible.map_or(usize::MAX, |max| max.get());
+    let notifications = store.visible().rev().take(max_visible);
+    show(ctx, notifications, store.kept_back(), images, drafts)
+}
+
+/// Lay out `notifications` as a vertical stack of cards, in the given order.
+///
+/// If `suppressed` is not zero, a card saying how many notifications Do Not
+/// Disturb kept back is shown on top.
+pub fn show<'a>(
+    ctx: &egui::Context,
+    notifications: impl Iterator<Item = &'a Notification>,
+    suppressed: usize,
+    images: &mut Images,
+    drafts: &mut Drafts,
+) -> Output {
+    let mut output = Output::default();
+    egui::CentralPanel::default()
+        .frame(Frame::none())
+        .show(ctx, |ui| {
+            ui.spacing_mut().item_spacing.y = 8.0;
+            if suppressed > 0 {
+                let response = summary(ui, suppressed);
+                output.revealed = response.clicked();
+                output.dismissed_suppressed = response.secondary_clicked();
+            }
+            for notification in notifications {
+                let id = notification.id;
+                let draft = drafts.drafts.entry(id).or_default();
+                let card = card(ui, notification, images, draft);
+                let response = card.response.interact(Sense::click());
+                if response.hovered() {
+                    output.hovered = Some(id);
+                }
+                if let Some(text) = card.reply {
+                    output.replied.push((id, text));
+                } else if let Some(key) = card.action {
+                    output.invoked.push((id, key));
+                } else if card.editing {
+                    // clicks on the reply field are not meant for the card
+                } else if response.clicked() {
+                    if let Some(url) = card.link {
+                        ctx.output_mut(|o| o.open_url = Some(OpenUrl::new_tab(url)));
+                    } else if notification.action("default").is_some() {
+                        output.invoked.push((id, "default".to_owned()));
+                    } else {
+                        output.dismissed.push(id);
+                    }
+                } else if response.secondary_clicked() {
+                    output.dismissed.push(id);
+                }
+            }
+            output.height = ui.min_rect().height();
+        });
+    // drafts of notifications without a reply field are always empty
+    drafts.drafts.retain(|_, draft| !draft.is_empty());
+    output.wants_keyboard = ctx.wants_keyboard_input();
+    output
+}
+
+fn card(ui: &mut Ui, notification: &Notification, images: &mut Images, draft: &mut String) -> Card {
+    let mut link = None;
+    let mut action = None;
+    let mut reply = None;
+    let mut editing = false;
+    let visuals = ui.visuals();
+    let style = notification.style;
+    let mut stroke = match notification.hints.urgency {
+        Urgency::Low => visuals.widgets.noninteractive.bg_stroke,
+        Urgency::Normal => visuals.window_stroke(),
+        Urgency::Critical => Stroke::new(2.0, visuals.error_fg_color),
+    };
+    if let Some(border) = style.border {
+        stroke.color = border.0;
+    }
+    let mut frame = Frame::window(ui.style())
+        .rounding(Rounding::same(8.0))
+        .shadow(Default::default())
+        .stroke(stroke);
+    if let Some(background) = style.background {
+        frame = frame.fill(background.0);
+    }
+    let response = frame
+        .show(ui, |ui| {
+            if let Some(text) = style.text {
+                ui.visuals_mut().override_text_color = Some(text.0);
+            }
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&notification.app_name).small().weak());
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    ui.label(RichText::new(age(notification.created_at)).small().weak());
+                });
+            });
+            ui.horizontal_top(|ui| {
+                if let Some(texture) = images.get(ui.ctx(), notification) {
+                    ui.image(texture, fit(texture.size_vec2(), IMAGE_SIZE));
+                }
+                ui.vertical(|ui| {
+                    let summary = RichText::new(&notification.summary).strong();
+                    ui.label(match notification.hints.urgency {
+                        Urgency::Low => summary.weak(),
+                        Urgency::Normal => summary,
+                        Urgency::Critical => summary.color(ui.visuals().error_fg_color),
+                    });
+                    if !notification.markup.is_empty() {
+                        link = body(ui, &notification.markup);
+                    }
+                    if let Some(value) = notification.hints.value {
+                        progress(ui, notification.id, value);
+                    }
+                });
+            });
+            action = actions(ui, notification, images);
+            if let Some(label) = notification.action(Notification::INLINE_REPLY) {
+                let placeholder = notification.hints.reply_placeholder.as_deref();
+                (reply, editing) = reply_field(ui, placeholder.unwrap_or(label), draft);
+            }
+        })
+        .response;
+    Card {
+        response,
+        link,
+        action,
+        reply,
+        editing,
+    }
+}
+
+/// Show how many notifications Do Not Disturb kept back.
+fn summary(ui: &mut Ui, count: usize) -> egui::Response {
+    Frame::window(ui.style())
+        .rounding(Rounding::same(8.0))
+        .shadow(Default::default())
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            let noun = if count == 1 {
+                "notification"
+            } else {
+                "notifications"
+            };
+            ui.label(RichText::new(format!("{count} {noun} while Do Not Disturb was on")).strong());
+            ui.label(
+                RichText::new("Click to show, right click to dismiss")
+                    .small()
+                    .weak(),
+            );
+        })
+        .response
+        .interact(Sense::click())
+}
+
+/// Show a notification body, returning the link under the pointer, if any.
+fn body(ui: &mut Ui, markup: &Markup) -> Option<String> {
+    let style = ui.style();
+    let font_id = FontSelection::Default.resolve(style);
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = ui.available_width();
+    for span in &markup.spans {
+        let mut format = TextFormat::simple(font_id.clone(), style.visuals.text_color());
+        if span.style.bold {
+            format.color = style.visuals.strong_text_color();
+        }
+        if span.style.link.is_some() {
+            format.color = style.visuals.hyperlink_color;
+        }
+        if span.style.underline || span.style.link.is_some() {
+            format.underline = Stroke::new(1.0, format.color);
+        }
+        format.italics = span.style.italic;
+        job.append(&span.text, 0.0, format);
+    }
+
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let (rect, response) = ui.allocate_exact_size(galley.size(), Sense::hover());
+    ui.painter().galley(rect.min, galley.clone());
+
+    let pos = response.hover_pos()? - rect.min.to_vec2();
+    let section = galley
+        .rows
+        .iter()
+        .filter(|row| row.rect.y_range().contains(&pos.y))
+        .flat_map(|row| &row.glyphs)
+        .find(|glyph| (glyph.pos.x..=glyph.max_x()).contains(&pos.x))?
+        .section_index;
+    let link = markup.spans[section as usize].style.link.clone()?;
+    ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+    Some(link)
+}
+
+/// Show the `value` hint as a progress bar.
+///
+/// Replacing the notification keeps its id, so the bar slides from the old
+/// value to the new one instead of jumping.
+fn progress(ui: &mut Ui, id: u32, value: u8) {
+    let value = ui.ctx().animate_value_with_time(
+        Id::new(("progress", id)),
+        f32::from(value) / 100.0,
+        PROGRESS_ANIMATION_TIME,
+    );
+    ui.add(ProgressBar::new(value).show_percentage());
+}
+
+/// Show the action buttons of a notification, returning the key of the one clicked.
+fn actions(ui: &mut Ui, notification: &Notification, images: &mut Images) -> Option<String> {
+    let actions = notification
+        .actions()
+        .filter(|(key, _)| *key != "default" && *key != Notification::INLINE_REPLY)
+        .collect::<Vec<_>>();
+    if actions.is_empty() {
+        return None;
+    }
+    let mut clicked = None;
+    ui.horizontal_wrapped(|ui| {
+        for (key, label) in actions {
+            let icon = notification
+                .hints
+                .action_icons
+                .then(|| images.action_icon(ui.ctx(), notification.id, key))
+                .flatten();
+            let response = match icon {
+                Some(icon) => ui
+                    .add(ImageButton::new(
+                        icon,
+                        fit(icon.size_vec2(), ACTION_ICON_SIZE),
+                    ))
+                    .on_hover_text(label),
+                None => ui.button(label),
+            };
+            if response.clicked() {
+                clicked = Some(key.to_owned());
+            }
+        }
+    });
+    clicked
+}
+
+/// Show the inline reply field, returning the text if it was sent with enter,
+/// and whether the field is hovered or focused.
+fn reply_field(ui: &mut Ui, placeholder: &str, draft: &mut String) -> (Option<String>, bool) {
+    let field = ui.add(
+        TextEdit::singleline(draft)
+            .hint_text(placeholder)
+            .desired_width(f32::INFINITY),
+    );
+    if field.has_focus() && ui.input(|i| i.key_pressed(Key::Escape)) {
+        ui.memory_mut(|mem| mem.surrender_focus(field.id));
+    }
+    // single line text edits give up focus on enter
+    let sent =
+        field.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) && !draft.trim().is_empty();
+    let reply = sent.then(|| std::mem::take(draft));
+    (reply, field.hovered() || field.has_focus())
+}
+
+/// Scale `size` down to fit in a `max` by `max` square, keeping the aspect ratio.
+fn fit(size: Vec2, max: f32) -> Vec2 {
+    size * (max / size.max_elem()).min(1.0)
+}
+
+/// A short human readable description of how long ago `time` was.
+fn age(time: SystemTime) -> String {
+    let secs = time.elapsed().unwrap_or_default().as_secs();
+    match secs {
+        0..=59 => "now".to_owned(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
@@ -1,40 +1,102 @@
+use std::io;
+use std::os::fd::AsRawFd;
 use std::sync::Arc;
+use std::time::Instant;
 
+use client::backend::WaylandError;
 use client::globals::registry_queue_init;
-use client::globals::{GlobalList, GlobalListContents};
+use client::globals::GlobalList;
 use client::protocol::*;
 use client::Connection;
-use client::{self, Dispatch, Proxy, QueueHandle};
-use egui::{Event, NumExt, Pos2, Ui, PointerButton};
-use glow::HasContext;
-use glutin::display::{Display, DisplayApiPreference, GetGlDisplay};
-use glutin::prelude::{GlDisplay, NotCurrentGlContextSurfaceAccessor};
-use glutin::surface::GlSurface;
-use protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1;
-use protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::ZwlrLayerSurfaceV1;
-use raw_window_handle::{
-    RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
-};
-use sctk::compositor::{CompositorHandler, CompositorState, Surface, SurfaceData};
-use sctk::globals::GlobalData;
+use client::{self, delegate_noop, Dispatch, Proxy, QueueHandle};
+use egui::{Event, Key, PointerButton, Pos2};
+use protocols::xdg::activation::v1::client::xdg_activation_token_v1::{self, XdgActivationTokenV1};
+use protocols::xdg::activation::v1::client::xdg_activation_v1::XdgActivationV1;
+use sctk::compositor::{CompositorHandler, CompositorState, Surface};
 use sctk::output::{OutputHandler, OutputState};
-use sctk::registry::{ProvidesRegistryState, RegistryHandler, RegistryState};
+use sctk::registry::{ProvidesRegistryState, RegistryState};
 use sctk::seat::keyboard::{keysyms, KeyEvent, KeyboardHandler, Modifiers};
-use sctk::seat::pointer::{PointerEvent, PointerEventKind, PointerHandler};
+use sctk::seat::pointer::{PointerData, PointerEvent, PointerEventKind, PointerHandler};
 use sctk::seat::{Capability, SeatHandler, SeatState};
 use sctk::shell::wlr_layer::{
-    Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
-    LayerSurfaceData,
+    Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface, SurfaceKind,
 };
 use sctk::shell::WaylandSurface;
-use sctk::{delegate_compositor, delegate_registry, delegate_seat, registry_handlers};
+use sctk::shm::{Shm, ShmHandler};
+use sctk::{
+    delegate_compositor, delegate_registry, delegate_seat, delegate_shm, registry_handlers,
+};
 use sctk::{delegate_keyboard, delegate_layer, delegate_output, delegate_pointer, reexports::*};
 use smithay_client_toolkit as sctk;
-use wl_compositor::WlCompositor;
-use wl_registry::WlRegistry;
-use wl_surface::WlSurface;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::config::{self, Config};
+use crate::icon::IconLoader;
+use crate::notification::{CloseReason, Message, Signal};
+use crate::render::{Gl, Renderer, Software};
+use crate::store::{Notifications, SharedStore};
+use crate::ui;
+
+/// The layer surface the notifications are drawn on.
+///
+/// It only exists while there is something to show, see [`State::redraw`].
+struct Popup {
+    layer: LayerSurface,
+    /// The size in logical pixels.
+    width: u32,
+    height: u32,
+    /// The scale of the outputs the surface is on.
+    scale: u32,
+    /// Whether the compositor sent the first configure, before which the
+    /// surface cannot be drawn on.
+    configured: bool,
+    /// Whether a frame callback was requested and not received yet.
+    frame_pending: bool,
+    /// Whether a redraw was asked for while waiting for the frame callback.
+    dirty: bool,
+    /// Whether egui asked to be repainted right away, e.g. for a progress bar
+    /// moving to its new value.
+    animating: bool,
+}
+
+impl Popup {
+    fn new(
+        compositor: &CompositorState,
+        layer_shell: &LayerShell,
+        qh: &QueueHandle<State>,
+        config: &Config,
+    ) -> color_eyre::Result<Self> {
+        let surface = Surface::new(compositor, qh)?;
+        let layer =
+            layer_shell.create_layer_surface(qh, surface, layer(config), Some("uwu-notify"), None);
+        configure_layer(&layer, config);
+        // asked for on demand while an inline reply is being typed, see `State::draw`
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        // start out as small as possible, `State::draw` resizes the surface to fit
+        let width = config.width.get();
+        let height = 1;
+        layer.set_size(width, height);
+        layer.commit();
+
+        Ok(Self {
+            layer,
+            width,
+            height,
+            scale: 1,
+            configured: false,
+            frame_pending: false,
+            dirty: false,
+            animating: false,
+        })
+    }
 
-type GlutinSurface = glutin::surface::Surface<glutin::surface::WindowSurface>;
+    /// The size of the buffers drawn onto the surface, in physical pixels.
+    fn buffer_size(&self) -> [u32; 2] {
+        [self.width * self.scale, self.height * self.scale]
+    }
+}
 
 pub struct State {
     // states
@@ -45,80 +107,377 @@ pub struct State {
     // things
     keyboard: Option<wl_keyboard::WlKeyboard>,
     keyboard_focus: bool,
+    /// Whether the layer surface currently asks for keyboard focus.
+    keyboard_interactive: bool,
     pointer: Option<wl_pointer::WlPointer>,
-    layer: LayerSurface,
+    /// The seat of the pointer, and the serial of the last button press.
+    last_press: Option<(wl_seat::WlSeat, u32)>,
+    activation: Option<XdgActivationV1>,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    popup: Option<Popup>,
+
+    config: Arc<Config>,
 
-    pub width: u32,
-    pub height: u32,
+    shm: Shm,
+    renderer: Box<dyn Renderer>,
+    egui: crate::paint::Egui,
 
-    gl: Arc<glow::Context>,
-    gl_context: glutin::context::PossiblyCurrentContext,
-    gl_surface: GlutinSurface,
-    painter: crate::paint::EguiGlow,
+    notifications: Notifications,
 
-    exit: bool,
+    images: ui::Images,
+    drafts: ui::Drafts,
+
+    /// The notification under the pointer, whose expiry is paused.
+    hovered: Option<u32>,
+    /// When egui asked to be repainted, e.g. to show a tooltip.
+    repaint_at: Option<Instant>,
+    /// Whether notifications were closed since the last [`State::tick`].
+    changed: bool,
+    /// Whether the event loop should stop, the surface being gone.
+    stopped: bool,
 }
 
 impl State {
-    pub fn new(
-        layer: LayerSurface,
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        config: Arc<Config>,
         global_list: &GlobalList,
         qh: &QueueHandle<Self>,
-        gl: glow::Context,
-        gl_context: glutin::context::PossiblyCurrentContext,
-        gl_surface: GlutinSurface,
+        compositor: CompositorState,
+        layer_shell: LayerShell,
+        shm: Shm,
+        popup: Popup,
+        renderer: Box<dyn Renderer>,
+        store: SharedStore,
+        signals: UnboundedSender<Signal>,
     ) -> Self {
-        let gl = Arc::new(gl);
-        let gl2 = gl.clone();
+        let egui = crate::paint::Egui::default();
+        ui::apply_config(&egui.egui_ctx, &config);
         Self {
-            layer,
-            exit: false,
             registry_state: RegistryState::new(global_list),
-            seat_state: SeatState::new(&global_list, qh),
-            output_state: OutputState::new(&global_list, qh),
+            seat_state: SeatState::new(global_list, qh),
+            output_state: OutputState::new(global_list, qh),
             keyboard: None,
             keyboard_focus: false,
-            width: 256,
-            height: 256,
+            keyboard_interactive: false,
             pointer: None,
-            gl,
-            gl_context,
-            gl_surface,
-            painter: crate::paint::EguiGlow::new(gl2, None),
+            last_press: None,
+            activation: global_list.bind(qh, 1..=1, ()).ok(),
+            compositor,
+            layer_shell,
+            popup: Some(popup),
+            shm,
+            renderer,
+            egui,
+            config,
+            notifications: Notifications::new(store, signals),
+            images: ui::Images::new(IconLoader::new(None)),
+            drafts: ui::Drafts::default(),
+            hovered: None,
+            repaint_at: None,
+            changed: false,
+            stopped: false,
         }
     }
 
-    pub fn wl_surface(&self) -> &wl_surface::WlSurface {
-        self.layer.wl_surface()
+    /// Close expired notifications, and redraw if any were closed or egui
+    /// asked to be repainted by now.
+    pub fn tick(&mut self, qh: &QueueHandle<Self>) {
+        self.expire();
+        if self.repaint_at.is_some_and(|at| at <= Instant::now()) {
+            self.repaint_at = None;
+            self.changed = true;
+        }
+        if std::mem::take(&mut self.changed) {
+            self.redraw(qh);
+        }
     }
 
-    pub fn draw(&mut self, qh: &QueueHandle<Self>) {
-        self.painter.run([self.width, self.height], |egui_ctx| {
-            egui::SidePanel::left("my_side_panel").show(egui_ctx, |ui| {
-                ui.heading("Hello World!");
-                if ui.button("something").clicked() {
-                    println!("hi");
+    /// When [`State::tick`] has something to do next.
+    pub fn next_tick(&self) -> Option<Instant> {
+        let expiry = self.notifications.store.lock().unwrap().next_expiry();
+        match (expiry, self.repaint_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Close expired notifications, not counting the time spent hovered.
+    pub fn expire(&mut self) {
+        self.changed |= self.notifications.expire(self.hovered);
+    }
+
+    /// Remove a notification from the store and emit `NotificationClosed` for it.
+    pub fn close(&mut self, id: u32, reason: CloseReason) {
+        self.changed |= self.notifications.close(id, reason);
+    }
+
+    /// Invoke an action the user clicked on.
+    ///
+    /// If the compositor supports it, an activation token is requested first
+    /// so the application can raise its window, see [`State::finish_invoke`].
+    pub fn invoke(&mut self, qh: &QueueHandle<Self>, id: u32, key: String) {
+        let Some((app_id, resident)) = self
+            .notifications
+            .store
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|notification| {
+                let hints = &notification.hints;
+                (hints.desktop_entry.clone(), hints.resident)
+            })
+        else {
+            return;
+        };
+        let (Some(activation), Some((seat, serial))) = (&self.activation, &self.last_press) else {
+            self.finish_invoke(id, key, resident);
+            return;
+        };
+        let pending = PendingAction { id, key, resident };
+        let token = activation.get_activation_token(qh, pending);
+        token.set_serial(*serial, seat);
+        if let Some(popup) = &self.popup {
+            token.set_surface(popup.layer.wl_surface());
+        }
+        if let Some(app_id) = app_id {
+            token.set_app_id(app_id);
+        }
+        token.commit();
+    }
+
+    /// Emit `ActionInvoked`, closing the notification unless it was resident.
+    ///
+    /// This happens even if the notification was closed or replaced while
+    /// waiting for the activation token, the user did click the action.
+    fn finish_invoke(&mut self, id: u32, key: String, resident: bool) {
+        self.changed |= self.notifications.invoke(id, key, resident);
+    }
+
+    /// Emit `NotificationReplied`, closing the notification unless it is resident.
+    pub fn reply(&mut self, id: u32, text: String) {
+        self.changed |= self.notifications.reply(id, text);
+    }
+
+    /// Close every live notification with the given reason.
+    pub fn close_all(&mut self, reason: CloseReason) {
+        self.changed |= self.notifications.close_all(reason);
+    }
+
+    /// Handle a message from the D-Bus side.
+    pub fn handle_message(&mut self, qh: &QueueHandle<Self>, message: Message) {
+        match message {
+            Message::Notify(id) => {
+                tracing::debug!(id, "notification added or replaced");
+                // the image might have changed
+                self.images.forget(id);
+            }
+            Message::Reload(config) => {
+                tracing::debug!("applying new config");
+                if let Some(popup) = &self.popup {
+                    configure_layer(&popup.layer, &config);
+                    // moving the surface to another layer needs version 2
+                    if matches!(popup.layer.kind(), SurfaceKind::Wlr(wlr) if wlr.version() >= 2) {
+                        popup.layer.set_layer(layer(&config));
+                    }
+                    popup.layer.set_size(config.width.get(), popup.height);
+                    popup.layer.commit();
+                }
+                ui::apply_config(&self.egui.egui_ctx, &config);
+                self.config = config;
+            }
+            Message::Redraw => {}
+            Message::Shutdown => {
+                tracing::info!("shutting down");
+                // normally closed on the D-Bus side already
+                self.close_all(CloseReason::Undefined);
+                if self.popup.is_some() {
+                    self.destroy_popup();
                 }
+                self.stopped = true;
+                return;
+            }
+        }
+        self.redraw(qh);
+    }
+
+    /// Draw what changed, as soon as the compositor is ready for it.
+    ///
+    /// Without a surface, one is created if there is anything to show, and
+    /// drawn once the compositor configured it.
+    pub fn redraw(&mut self, qh: &QueueHandle<Self>) {
+        let Some(popup) = &mut self.popup else {
+            if self.notifications.store.lock().unwrap().showing() {
+                self.create_popup(qh);
+            }
+            return;
+        };
+        if !popup.configured {
+            return;
+        }
+        if popup.frame_pending {
+            popup.dirty = true;
+        } else {
+            self.draw(qh);
+        }
+    }
+
+    fn create_popup(&mut self, qh: &QueueHandle<Self>) {
+        tracing::debug!("creating the surface");
+        let popup =
+            Popup::new(&self.compositor, &self.layer_shell, qh, &self.config).and_then(|popup| {
+                let surface = popup.layer.wl_surface();
+                let [width, height] = popup.buffer_size();
+                self.renderer.attach(surface, width, height)?;
+                Ok(popup)
             });
+        match popup {
+            Ok(popup) => {
+                // the previous surface might have been on another output
+                self.set_scale(popup.scale);
+                self.popup = Some(popup);
+            }
+            Err(err) => tracing::error!("failed to create the surface: {err}"),
+        }
+    }
+
+    /// Draw at the given scale from now on, the icons having to be loaded
+    /// again at the new size.
+    fn set_scale(&mut self, scale: u32) {
+        let current = self.egui.input.pixels_per_point;
+        if current.unwrap_or_else(|| self.egui.egui_ctx.pixels_per_point()) == scale as f32 {
+            return;
+        }
+        self.egui.input.pixels_per_point = Some(scale as f32);
+        self.images.rescale(scale);
+    }
+
+    /// Destroy the surface, there being nothing to show on it.
+    fn destroy_popup(&mut self) {
+        tracing::debug!("destroying the surface");
+        // in this order, the EGL surface must not outlive the wl_surface it
+        // draws on, see also `impl Drop for State`
+        self.renderer.detach();
+        self.popup = None;
+        self.keyboard_focus = false;
+        self.keyboard_interactive = false;
+        self.hovered = None;
+        self.repaint_at = None;
+        self.egui.input.events.clear();
+    }
+
+    pub fn draw(&mut self, qh: &QueueHandle<Self>) {
+        let Some(mut popup) = self.popup.take() else {
+            return;
+        };
+        let store = self.notifications.store.lock().unwrap();
+        if !store.showing() {
+            drop(store);
+            self.destroy_popup();
+            return;
+        }
+        // free the textures of notifications closed through D-Bus or expired
+        self.images.retain(|id| store.get(id).is_some());
+        self.drafts.retain(|id| store.get(id).is_some());
+        let images = &mut self.images;
+        let drafts = &mut self.drafts;
+        let config = &self.config;
+        let mut output = ui::Output::default();
+        let repaint_after = self.egui.run([popup.width, popup.height], |egui_ctx| {
+            output = ui::show_store(egui_ctx, &store, config, images, drafts);
         });
-        unsafe {
-            self.gl.clear_color(1.0, 0.1, 0.1, 1.0);
-            self.gl.clear(glow::COLOR_BUFFER_BIT);
-            self.gl.flush();
-            self.painter.paint([self.width, self.height]);
+        drop(store);
+
+        // keep drawing every frame while animating, otherwise only once egui
+        // asks for it or something changes
+        popup.animating = repaint_after.is_zero();
+        self.repaint_at = if popup.animating {
+            None
+        } else {
+            Instant::now().checked_add(repaint_after)
+        };
+
+        // only take keyboard focus while typing a reply, so that showing
+        // notifications never steals it from the focused window
+        if output.wants_keyboard != self.keyboard_interactive {
+            self.keyboard_interactive = output.wants_keyboard;
+            popup
+                .layer
+                .set_keyboard_interactivity(if output.wants_keyboard {
+                    KeyboardInteractivity::OnDemand
+                } else {
+                    KeyboardInteractivity::None
+                });
+        }
+
+        // grow or shrink the surface to fit the stack, the compositor will
+        // follow up with a configure event with the new size
+        let height = output.surface_height(&self.config);
+        if height != popup.height {
+            popup.layer.set_size(self.config.width.get(), height);
+        }
+
+        let surface = self.config.colors.surface.map(|color| color.0);
+        let wl_surface = popup.layer.wl_surface();
+        let [width, height] = popup.buffer_size();
+        self.renderer.paint(
+            wl_surface,
+            [width, height],
+            self.egui.frame(),
+            surface.unwrap_or_default(),
+        );
+        wl_surface.damage_buffer(0, 0, width as i32, height as i32);
+        // the next draw waits for the frame callback, so that drawing is
+        // never faster than the compositor can show it
+        if !popup.frame_pending {
+            popup.frame_pending = true;
+            wl_surface.frame(qh, wl_surface.clone());
+        }
+        popup.dirty = false;
+        popup.layer.commit();
+        self.popup = Some(popup);
 
-            // draw things on top of egui here
+        if output.revealed {
+            self.notifications.store.lock().unwrap().reveal_suppressed();
+            self.changed = true;
+        }
+        if output.dismissed_suppressed {
+            let ids = self.notifications.store.lock().unwrap().suppressed();
+            for id in ids {
+                self.close(id, CloseReason::Dismissed);
+            }
+        }
 
-            self.gl_surface.swap_buffers(&self.gl_context).unwrap();
+        if output.hovered != self.hovered {
+            // count the time until now as hovered or not before switching
+            self.expire();
+            self.hovered = output.hovered;
+        }
+        for id in output.dismissed {
+            self.close(id, CloseReason::Dismissed);
+        }
+        for (id, key) in output.invoked {
+            self.invoke(qh, id, key);
         }
-        self.layer
-            .wl_surface()
-            .damage_buffer(0, 0, self.width as i32, self.height as i32);
-        self.layer
-            .wl_surface()
-            .frame(qh, self.layer.wl_surface().clone());
+        for (id, text) in output.replied {
+            self.reply(id, text);
+        }
+    }
+
+    /// Whether `surface` is the one the notifications are shown on.
+    fn is_popup(&self, surface: &wl_surface::WlSurface) -> bool {
+        self.popup
+            .as_ref()
+            .is_some_and(|popup| popup.layer.wl_surface() == surface)
+    }
+}
 
-        self.layer.commit();
+impl Drop for State {
+    fn drop(&mut self) {
+        // fields drop in declaration order, which would destroy the
+        // wl_surface of the popup before the EGL surface on it
+        self.renderer.detach();
     }
 }
 
@@ -137,12 +496,12 @@ impl SeatHandler for State {
         &mut self.seat_state
     }
 
-    fn new_seat(&mut self, conn: &Connection, qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {}
-    fn remove_seat(&mut self, conn: &Connection, qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {}
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
 
     fn new_capability(
         &mut self,
-        conn: &Connection,
+        _: &Connection,
         qh: &QueueHandle<Self>,
         seat: wl_seat::WlSeat,
         capability: Capability,
@@ -161,6 +520,7 @@ impl SeatHandler for State {
                     .get_pointer(qh, &seat)
                     .expect("Failed to create pointer");
                 self.pointer = Some(pointer);
+                self.last_press = None;
             }
             _ => {}
         }
@@ -168,9 +528,9 @@ impl SeatHandler for State {
 
     fn remove_capability(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        seat: wl_seat::WlSeat,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
         capability: Capability,
     ) {
         match capability {
@@ -200,10 +560,10 @@ impl KeyboardHandler for State {
         surface: &wl_surface::WlSurface,
         _: u32,
         _: &[u32],
-        keysyms: &[u32],
+        _: &[u32],
     ) {
-        if self.layer.wl_surface() == surface {
-            println!("Keyboard focus on window with pressed syms: {keysyms:?}");
+        if self.is_popup(surface) {
+            tracing::debug!("keyboard focus entered");
             self.keyboard_focus = true;
         }
     }
@@ -211,42 +571,66 @@ impl KeyboardHandler for State {
     fn leave(
         &mut self,
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         _: &wl_keyboard::WlKeyboard,
         surface: &wl_surface::WlSurface,
         _: u32,
     ) {
-        if self.layer.wl_surface() == surface {
-            println!("Release keyboard focus on window");
+        if self.is_popup(surface) {
+            tracing::debug!("keyboard focus left");
             self.keyboard_focus = false;
+            // give the keyboard back, the next draw drops the interactivity
+            self.egui.egui_ctx.memory_mut(|mem| mem.stop_text_input());
+            self.redraw(qh);
         }
     }
 
     fn press_key(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         _: &wl_keyboard::WlKeyboard,
         _: u32,
         event: KeyEvent,
     ) {
-        println!("Key press: {event:?}");
-        // press 'esc' to exit
-        // TODO
-        if event.keysym == keysyms::XKB_KEY_Escape {
-            self.exit = true;
+        let input = &mut self.egui.input;
+        if let Some(key) = key(event.keysym) {
+            input.events.push(Event::Key {
+                key,
+                pressed: true,
+                repeat: false,
+                modifiers: input.modifiers,
+            });
         }
+        // shortcuts are not text
+        let shortcut = input.modifiers.ctrl || input.modifiers.alt;
+        if let Some(text) = event
+            .utf8
+            .filter(|text| !shortcut && !text.chars().any(char::is_control))
+        {
+            input.events.push(Event::Text(text));
+        }
+        self.redraw(qh);
     }
 
     fn release_key(
         &mut self,
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         _: &wl_keyboard::WlKeyboard,
         _: u32,
         event: KeyEvent,
     ) {
-        println!("Key release: {event:?}");
+        let input = &mut self.egui.input;
+        if let Some(key) = key(event.keysym) {
+            input.events.push(Event::Key {
+                key,
+                pressed: false,
+                repeat: false,
+                modifiers: input.modifiers,
+            });
+            self.redraw(qh);
+        }
     }
 
     fn update_modifiers(
@@ -257,7 +641,35 @@ impl KeyboardHandler for State {
         _serial: u32,
         modifiers: Modifiers,
     ) {
-        println!("Update modifiers: {modifiers:?}");
+        self.egui.input.modifiers = egui::Modifiers {
+            alt: modifiers.alt,
+            ctrl: modifiers.ctrl,
+            shift: modifiers.shift,
+            mac_cmd: false,
+            command: modifiers.ctrl,
+        };
+    }
+}
+
+/// Map a keysym to the egui key text fields care about.
+fn key(keysym: u32) -> Option<Key> {
+    match keysym {
+        keysyms::XKB_KEY_Return | keysyms::XKB_KEY_KP_Enter => Some(Key::Enter),
+        keysyms::XKB_KEY_Escape => Some(Key::Escape),
+        keysyms::XKB_KEY_Tab => Some(Key::Tab),
+        keysyms::XKB_KEY_BackSpace => Some(Key::Backspace),
+        keysyms::XKB_KEY_Delete => Some(Key::Delete),
+        keysyms::XKB_KEY_Left => Some(Key::ArrowLeft),
+        keysyms::XKB_KEY_Right => Some(Key::ArrowRight),
+        keysyms::XKB_KEY_Up => Some(Key::ArrowUp),
+        keysyms::XKB_KEY_Down => Some(Key::ArrowDown),
+        keysyms::XKB_KEY_Home => Some(Key::Home),
+        keysyms::XKB_KEY_End => Some(Key::End),
+        // select all, undo and redo
+        keysyms::XKB_KEY_a | keysyms::XKB_KEY_A => Some(Key::A),
+        keysyms::XKB_KEY_z | keysyms::XKB_KEY_Z => Some(Key::Z),
+        keysyms::XKB_KEY_y | keysyms::XKB_KEY_Y => Some(Key::Y),
+        _ => None,
     }
 }
 
@@ -267,62 +679,111 @@ impl PointerHandler for State {
     fn pointer_frame(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _pointer: &wl_pointer::WlPointer,
+        qh: &QueueHandle<Self>,
+        pointer: &wl_pointer::WlPointer,
         events: &[PointerEvent],
     ) {
         use PointerEventKind::*;
+        let mut redraw = false;
         for event in events {
             // Ignore events for other surfaces
-            if &event.surface != self.layer.wl_surface() {
+            if !self.is_popup(&event.surface) {
                 continue;
             }
+            redraw = true;
             match event.kind {
                 Enter { .. } => {
-                    println!("Pointer entered @{:?}", event.position);
+                    tracing::trace!(position = ?event.position, "pointer entered");
                 }
                 Leave { .. } => {
-                    self.painter.input.events.push(Event::PointerGone);
-                    println!("Pointer left");
+                    self.egui.input.events.push(Event::PointerGone);
+                    tracing::trace!("pointer left");
                 }
-                Motion { .. } => self
-                    .painter
-                    .input
-                    .events
-                    .push(Event::PointerMoved(Pos2::new(
-                        event.position.0 as f32,
-                        event.position.1 as f32,
-                    ))),
-                Press { button, .. } => {
-                    self.painter.input.events.push(Event::PointerButton {
-                        pos: Pos2::new(event.position.0 as f32, event.position.1 as f32),
-                        // TOOD lol, lmao
-                        button: PointerButton::Primary,
-                        pressed: true,
-                        // TODO impl modifiers
-                        modifiers: egui::Modifiers::default(),
-                    });
-                    println!("Press {:x} @ {:?}", button, event.position);
+                Motion { .. } => self.egui.input.events.push(Event::PointerMoved(Pos2::new(
+                    event.position.0 as f32,
+                    event.position.1 as f32,
+                ))),
+                Press { button, serial, .. } => {
+                    let seat = pointer
+                        .data::<PointerData>()
+                        .map(|data| data.seat().clone());
+                    self.last_press = seat.map(|seat| (seat, serial));
+                    if let Some(button) = pointer_button(button) {
+                        self.egui.input.events.push(Event::PointerButton {
+                            pos: Pos2::new(event.position.0 as f32, event.position.1 as f32),
+                            button,
+                            pressed: true,
+                            // TODO impl modifiers
+                            modifiers: egui::Modifiers::default(),
+                        });
+                    }
+                    tracing::trace!(button, position = ?event.position, "pointer button pressed");
                 }
                 Release { button, .. } => {
-                    self.painter.input.events.push(Event::PointerButton {
-                        pos: Pos2::new(event.position.0 as f32, event.position.1 as f32),
-                        // TOOD lol, lmao
-                        button: PointerButton::Primary,
-                        pressed: false,
-                        modifiers: egui::Modifiers::default(),
-                    });
-                    println!("Release {:x} @ {:?}", button, event.position);
+                    if let Some(button) = pointer_button(button) {
+                        self.egui.input.events.push(Event::PointerButton {
+                            pos: Pos2::new(event.position.0 as f32, event.position.1 as f32),
+                            button,
+                            pressed: false,
+                            modifiers: egui::Modifiers::default(),
+                        });
+                    }
+                    tracing::trace!(button, position = ?event.position, "pointer button released");
                 }
                 Axis {
                     horizontal,
                     vertical,
                     ..
                 } => {
-                    println!("Scroll H:{horizontal:?}, V:{vertical:?}");
+                    tracing::trace!(?horizontal, ?vertical, "scrolled");
                 }
             }
         }
+        if redraw {
+            self.redraw(qh);
+        }
+    }
+}
+
+/// Map a linux input event code (`BTN_*`) to an egui button.
+fn pointer_button(button: u32) -> Option<PointerButton> {
+    match button {
+        0x110 => Some(PointerButton::Primary),
+        0x111 => Some(PointerButton::Secondary),
+        0x112 => Some(PointerButton::Middle),
+        0x113 => Some(PointerButton::Extra1),
+        0x114 => Some(PointerButton::Extra2),
+        _ => None,
+    }
+}
+
+/// An action waiting for its activation token, see [`State::invoke`].
+pub struct PendingAction {
+    id: u32,
+    key: String,
+    /// Whether the notification was resident when the action was clicked.
+    resident: bool,
+}
+
+delegate_noop!(State: XdgActivationV1);
+
+impl Dispatch<XdgActivationTokenV1, PendingAction> for State {
+    fn event(
+        state: &mut Self,
+        token: &XdgActivationTokenV1,
+        event: xdg_activation_token_v1::Event,
+        data: &PendingAction,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_activation_token_v1::Event::Done {
+            token: activation_token,
+        } = event
+        {
+            state.notifications.signal(Signal::ActivationToken(data.id, activation_token));
+            state.finish_invoke(data.id, data.key.clone(), data.resident);
+            token.destroy();
+        }
     }
 }
 
@@ -333,29 +794,11 @@ impl OutputHandler for State {
         &mut self.output_state
     }
 
-    fn new_output(
-        &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        output: wl_output::WlOutput,
-    ) {
-    }
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
 
-    fn update_output(
-        &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        output: wl_output::WlOutput,
-    ) {
-    }
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
 
-    fn output_destroyed(
-        &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        output: wl_output::WlOutput,
-    ) {
-    }
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
 }
 
 delegate_layer!(State);
@@ -363,58 +806,144 @@ delegate_layer!(State);
 impl LayerShellHandler for State {
     fn closed(
         &mut self,
-        conn: &Connection,
-        qh: &client::QueueHandle<Self>,
+        _: &Connection,
+        _: &client::QueueHandle<Self>,
         layer: &sctk::shell::wlr_layer::LayerSurface,
     ) {
+        if !self.is_popup(layer.wl_surface()) {
+            return;
+        }
+        // the compositor took our surface away, so nothing is being displayed
+        // anymore, a new one is created for the next notification
+        self.close_all(CloseReason::Undefined);
+        self.destroy_popup();
     }
     fn configure(
         &mut self,
-        conn: &Connection,
+        _: &Connection,
         qh: &client::QueueHandle<Self>,
         layer: &sctk::shell::wlr_layer::LayerSurface,
         configure: sctk::shell::wlr_layer::LayerSurfaceConfigure,
-        serial: u32,
+        _serial: u32,
     ) {
+        let Some(popup) = &mut self.popup else {
+            return;
+        };
+        if popup.layer.wl_surface() != layer.wl_surface() {
+            return;
+        }
         if configure.new_size.0 == 0 || configure.new_size.1 == 0 {
-            self.width = 256;
-            self.height = 256;
+            // the compositor leaves the size up to us
+            popup.width = self.config.width.get();
+            popup.height = 1;
         } else {
-            self.width = configure.new_size.0;
-            self.height = configure.new_size.1;
+            popup.width = configure.new_size.0;
+            popup.height = configure.new_size.1;
         }
+        let [width, height] = popup.buffer_size();
+        self.renderer.resize(width, height);
+        popup.configured = true;
         self.draw(qh)
     }
 }
 
 delegate_registry!(State);
 delegate_compositor!(State);
+delegate_shm!(State);
+
+impl ShmHandler for State {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
 
 impl CompositorHandler for State {
+    /// Follow the scale of the outputs the surface is on, drawing at their
+    /// resolution rather than having the compositor upscale a blurry buffer.
     fn scale_factor_changed(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
-        // TODO
-        // Not needed for this example.
+        let Some(popup) = &mut self.popup else {
+            return;
+        };
+        // the buffer scale needs version 3
+        if popup.layer.wl_surface() != surface || surface.version() < 3 {
+            return;
+        }
+        let scale = new_factor.max(1) as u32;
+        if scale == popup.scale {
+            return;
+        }
+        tracing::debug!(scale, "output scale changed");
+        popup.scale = scale;
+        surface.set_buffer_scale(scale as i32);
+        let [width, height] = popup.buffer_size();
+        self.renderer.resize(width, height);
+        self.set_scale(scale);
+        self.redraw(qh);
     }
 
     fn frame(
         &mut self,
         _conn: &Connection,
         qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
+        surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
-        // TODO
-        self.draw(qh);
+        let Some(popup) = &mut self.popup else {
+            return;
+        };
+        if popup.layer.wl_surface() != surface {
+            return;
+        }
+        popup.frame_pending = false;
+        if popup.dirty || popup.animating {
+            self.draw(qh);
+        }
     }
 }
 
-pub fn main() -> color_eyre::Result<()> {
+fn layer(config: &Config) -> Layer {
+    match config.layer {
+        config::Layer::Background => Layer::Background,
+        config::Layer::Bottom => Layer::Bottom,
+        config::Layer::Top => Layer::Top,
+        config::Layer::Overlay => Layer::Overlay,
+    }
+}
+
+/// Set up where the notifications are shown.
+fn configure_layer(layer: &LayerSurface, config: &Config) {
+    layer.set_anchor(match config.anchor {
+        config::Anchor::TopLeft => Anchor::TOP | Anchor::LEFT,
+        config::Anchor::Top => Anchor::TOP,
+        config::Anchor::TopRight => Anchor::TOP | Anchor::RIGHT,
+        config::Anchor::Left => Anchor::LEFT,
+        config::Anchor::Center => Anchor::empty(),
+        config::Anchor::Right => Anchor::RIGHT,
+        config::Anchor::BottomLeft => Anchor::BOTTOM | Anchor::LEFT,
+        config::Anchor::Bottom => Anchor::BOTTOM,
+        config::Anchor::BottomRight => Anchor::BOTTOM | Anchor::RIGHT,
+    });
+    let margin = config.margin;
+    layer.set_margin(margin.top, margin.right, margin.bottom, margin.left);
+}
+
+/// Show notifications until the D-Bus side goes away.
+///
+/// This runs on the same thread as the D-Bus side, the Wayland socket being
+/// polled by tokio, so the loop only wakes up for Wayland events, messages
+/// from the D-Bus side and notifications expiring.
+pub async fn main(
+    config: Arc<Config>,
+    store: SharedStore,
+    mut rx: UnboundedReceiver<Message>,
+    signals: UnboundedSender<Signal>,
+) -> color_eyre::Result<()> {
     let conn = Connection::connect_to_env()?;
     let (globals, queue) = registry_queue_init::<State>(&conn)?;
     let handle = queue.handle();
@@ -422,114 +951,74 @@ pub fn main() -> color_eyre::Result<()> {
     let compositor = CompositorState::bind(&globals, &handle)?;
     let layer_shell = LayerShell::bind(&globals, &handle)?;
 
-    let surface = Surface::new(&compositor, &handle)?;
-    let layer_surface =
-        layer_shell.create_layer_surface(&handle, surface, Layer::Top, Some("uwu-notify"), None);
-
-    layer_surface.set_anchor(Anchor::TOP | Anchor::RIGHT);
-    // TODO do we need keyboard interactivity?
-    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
-    // TODO
-    let width = 300;
-    let height = 300;
-    layer_surface.set_size(width, height);
-
-    layer_surface.commit();
-
-    let display = conn.display();
-    let ptr = display.id().as_ptr();
-
-    let mut wl = WaylandDisplayHandle::empty();
-    wl.display = ptr.cast();
-    let display =
-        unsafe { Display::new(RawDisplayHandle::Wayland(wl), DisplayApiPreference::Egl)? };
-
-    let mut window_handle = WaylandWindowHandle::empty();
-    window_handle.surface = layer_surface.wl_surface().id().as_ptr().cast();
-    let window_handle = RawWindowHandle::Wayland(window_handle);
-
-    let config_template_builder = glutin::config::ConfigTemplateBuilder::new()
-        .prefer_hardware_accelerated(None)
-        .with_depth_size(0)
-        .with_stencil_size(0)
-        .with_transparency(false);
-
-    let configs = unsafe { display.find_configs(config_template_builder.build()) };
-    let gl_config = configs?.next().unwrap();
-    let gl_display = gl_config.display();
-    let context_attributes =
-        glutin::context::ContextAttributesBuilder::new().build(Some(window_handle));
-    // by default, glutin will try to create a core opengl context. but, if it is not available, try to create a gl-es context using this fallback attributes
-    let fallback_context_attributes = glutin::context::ContextAttributesBuilder::new()
-        .with_context_api(glutin::context::ContextApi::Gles(None))
-        .build(Some(window_handle));
-    let not_current_gl_context = unsafe {
-        gl_display
-            .create_context(&gl_config, &context_attributes)
-            .unwrap_or_else(|_| {
-                        tracing::debug!("failed to create gl_context with attributes: {:?}. retrying with fallback context attributes: {:?}",
-                            &context_attributes,
-                            &fallback_context_attributes);
-                        gl_config
-                            .display()
-                            .create_context(&gl_config, &fallback_context_attributes)
-                            .expect("failed to create context even with fallback attributes")
-        })
-    };
+    let shm = Shm::bind(&globals, &handle)?;
 
-    let width = std::num::NonZeroU32::new(width.at_least(1)).unwrap();
-    let height = std::num::NonZeroU32::new(height.at_least(1)).unwrap();
-    let surface_attributes = glutin::surface::SurfaceAttributesBuilder::<
-        glutin::surface::WindowSurface,
-    >::new()
-    .build(window_handle, width, height);
-    tracing::debug!(
-        "creating surface with attributes: {:?}",
-        &surface_attributes
-    );
-    let gl_surface = unsafe {
-        gl_display
-            .create_window_surface(&gl_config, &surface_attributes)
-            .unwrap()
+    let mut popup = Popup::new(&compositor, &layer_shell, &handle, &config)?;
+    let surface = popup.layer.wl_surface();
+    let renderer: Box<dyn Renderer> = match config.renderer {
+        config::Renderer::Auto => match Gl::new(&conn, surface, popup.width, popup.height) {
+            Ok(gl) => Box::new(gl),
+            Err(err) => {
+                tracing::warn!("failed to set up OpenGL, drawing on the CPU instead: {err:#}");
+                // start over on a surface EGL did not touch
+                popup = Popup::new(&compositor, &layer_shell, &handle, &config)?;
+                Box::new(Software::new(&shm)?)
+            }
+        },
+        config::Renderer::Gl => Box::new(Gl::new(&conn, surface, popup.width, popup.height)?),
+        config::Renderer::Software => Box::new(Software::new(&shm)?),
     };
-    tracing::debug!("surface created successfully: {gl_surface:?}.making context current");
-    let gl_context = not_current_gl_context.make_current(&gl_surface).unwrap();
-
-    gl_surface
-        .set_swap_interval(
-            &gl_context,
-            glutin::surface::SwapInterval::Wait(std::num::NonZeroU32::new(1).unwrap()),
-        )
-        .unwrap();
-
-    let glow_context =
-        unsafe { glow::Context::from_loader_function_cstr(|x| gl_display.get_proc_address(x)) };
 
     let mut queue = queue;
     let mut state = State::new(
-        layer_surface,
+        config,
         &globals,
         &handle,
-        glow_context,
-        gl_context,
-        gl_surface,
+        compositor,
+        layer_shell,
+        shm,
+        popup,
+        renderer,
+        store,
+        signals,
     );
-    /*
-    queue.roundtrip(&mut state)?;
-    unsafe {
-        glow_context.clear_color(1.0, 0.1, 0.1, 1.0);
-        glow_context.clear(1);
-    }
-    gl_surface.swap_buffers(&gl_context)?;
-    state.wl_surface().commit();*/
 
-    //let painter = egui_glow::Painter::new(Arc::new(glow_context), "", None)
-    //    .map_err(|x| color_eyre::eyre::eyre!("GL error: {x}"))?;
+    let fd = queue.prepare_read()?.connection_fd().as_raw_fd();
+    let fd = AsyncFd::with_interest(fd, Interest::READABLE | Interest::WRITABLE)?;
+    loop {
+        queue.dispatch_pending(&mut state)?;
+        if state.stopped {
+            // send the destruction of the surface before going away
+            queue.flush()?;
+            return Ok(());
+        }
+        state.tick(&handle);
+        let flushed = match queue.flush() {
+            // the socket is full, the rest is sent once it is writable again
+            Err(WaylandError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => false,
+            result => result.map(|()| true)?,
+        };
 
-    while !state.exit {
-        // TODO use calloop
-        queue.blocking_dispatch(&mut state)?;
+        let read = queue.prepare_read()?;
+        let wakeup = state.next_tick();
+        let tick = tokio::time::sleep_until(wakeup.unwrap_or_else(Instant::now).into());
+        tokio::select! {
+            ready = fd.readable() => {
+                let mut ready = ready?;
+                match read.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                        ready.clear_ready();
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            ready = fd.writable(), if !flushed => ready?.clear_ready(),
+            message = rx.recv() => match message {
+                Some(message) => state.handle_message(&handle, message),
+                None => return Ok(()),
+            },
+            _ = tick, if wakeup.is_some() => {}
+        }
     }
-
-    Ok(())
 }
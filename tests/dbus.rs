@@ -0,0 +1,631 @@
+//! Conformance tests of the D-Bus interfaces against a private bus.
+//!
+//! Every test starts its own `dbus-daemon --session` and serves on it without
+//! showing anything, so neither a desktop nor a session bus is needed, only
+//! `dbus-daemon` on the `PATH`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::panic;
+use std::process::{self, Child, Command, Stdio};
+use std::sync::Once;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use futures_util::{Stream, StreamExt};
+use uwu_notify::daemon::{self, Frontend, Options};
+use uwu_notify::Entry;
+use zbus::fdo::{DBusProxy, RequestNameFlags};
+use zbus::names::OwnedUniqueName;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{Connection, ConnectionBuilder};
+
+const NAME: &str = "org.freedesktop.Notifications";
+
+/// How long to wait for something that should happen.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+    fn get_capabilities(&self) -> zbus::Result<Vec<String>>;
+    fn get_server_information(&self) -> zbus::Result<(String, String, String, String)>;
+    #[dbus_proxy(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: String) -> zbus::Result<()>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "rs.uwu.Notify",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/rs/uwu/Notify"
+)]
+trait Control {
+    fn dismiss(&self, id: u32) -> zbus::Result<()>;
+    fn invoke(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+    fn count(&self) -> zbus::Result<(u32, u32)>;
+    fn notifications(&self) -> zbus::Result<Vec<Entry>>;
+    fn hints(&self, id: u32) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "rs.uwu.Notify.History",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/rs/uwu/Notify"
+)]
+trait History {
+    fn list(&self, offset: u32, limit: u32) -> zbus::Result<Vec<Entry>>;
+}
+
+/// A private session bus, stopped when dropped.
+struct Bus {
+    process: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Self {
+        let mut process = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start dbus-daemon, is it installed?");
+        let mut address = String::new();
+        BufReader::new(process.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Self {
+            process,
+            address: address.trim().to_owned(),
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Method calls that made the daemon panic are never answered, so rather than
+/// letting the test wait forever, take the whole test binary down.
+fn abort_on_daemon_panic() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let report = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            report(info);
+            if thread::current().name() == Some("daemon") {
+                process::abort();
+            }
+        }));
+    });
+}
+
+/// Serve on the bus at `address` without showing anything or keeping files.
+fn options(address: &str) -> Options {
+    Options {
+        address: Some(address.to_owned()),
+        config: None,
+        history: None,
+        replace: false,
+        frontend: Frontend::Null,
+    }
+}
+
+fn spawn(options: Options) -> JoinHandle<color_eyre::Result<()>> {
+    abort_on_daemon_panic();
+    // like in the binary, the daemon needs a runtime on a thread of its own
+    let daemon = thread::Builder::new().name("daemon".to_owned());
+    daemon
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(daemon::run(options))
+        })
+        .unwrap()
+}
+
+/// The connection owning the bus name, once there is one.
+async fn owner(conn: &Connection) -> OwnedUniqueName {
+    let dbus = DBusProxy::new(conn).await.unwrap();
+    let start = Instant::now();
+    loop {
+        if let Ok(owner) = dbus.get_name_owner(NAME.try_into().unwrap()).await {
+            return owner;
+        }
+        assert!(start.elapsed() < TIMEOUT, "nobody took the name");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// The daemon serving on a private bus, and a connection to talk to it.
+struct Daemon {
+    conn: Connection,
+    thread: JoinHandle<color_eyre::Result<()>>,
+    // dropped last, the daemon gives up once the bus is gone
+    bus: Bus,
+}
+
+impl Daemon {
+    async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    async fn start_with(configure: impl FnOnce(&mut Options)) -> Self {
+        let bus = Bus::start();
+        let mut options = options(&bus.address);
+        configure(&mut options);
+        let thread = spawn(options);
+        let conn = ConnectionBuilder::address(&*bus.address)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        owner(&conn).await;
+        Self { conn, thread, bus }
+    }
+
+    async fn notifications(&self) -> NotificationsProxy<'static> {
+        NotificationsProxy::new(&self.conn).await.unwrap()
+    }
+
+    async fn control(&self) -> ControlProxy<'static> {
+        ControlProxy::new(&self.conn).await.unwrap()
+    }
+
+    async fn history(&self) -> HistoryProxy<'static> {
+        HistoryProxy::new(&self.conn).await.unwrap()
+    }
+}
+
+/// Send a notification that does not expire on its own.
+async fn notify(
+    proxy: &NotificationsProxy<'_>,
+    replaces_id: u32,
+    summary: &str,
+    actions: &[&str],
+    hints: HashMap<&str, Value<'_>>,
+) -> u32 {
+    proxy
+        .notify("tests", replaces_id, "", summary, "", actions, hints, 0)
+        .await
+        .unwrap()
+}
+
+async fn next<T>(stream: &mut (impl Stream<Item = T> + Unpin)) -> T {
+    tokio::time::timeout(TIMEOUT, stream.next())
+        .await
+        .expect("timed out waiting for a signal")
+        .unwrap()
+}
+
+async fn closed(stream: &mut NotificationClosedStream<'_>) -> (u32, u32) {
+    let signal = next(stream).await;
+    let args = signal.args().unwrap();
+    (args.id, args.reason)
+}
+
+async fn invoked(stream: &mut ActionInvokedStream<'_>) -> (u32, String) {
+    let signal = next(stream).await;
+    let args = signal.args().unwrap();
+    (args.id, args.action_key)
+}
+
+async fn entry(control: &ControlProxy<'_>, id: u32) -> Entry {
+    control
+        .notifications()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .unwrap_or_else(|| panic!("notification {id} is not live"))
+}
+
+#[tokio::test]
+async fn server_information() {
+    let daemon = Daemon::start().await;
+    let (name, vendor, version, spec_version) = daemon
+        .notifications()
+        .await
+        .get_server_information()
+        .await
+        .unwrap();
+    assert_eq!(name, "uwu-notify");
+    assert_eq!(vendor, env!("CARGO_PKG_AUTHORS"));
+    assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(spec_version, "1.2");
+}
+
+#[tokio::test]
+async fn capabilities() {
+    let daemon = Daemon::start().await;
+    let mut capabilities = daemon
+        .notifications()
+        .await
+        .get_capabilities()
+        .await
+        .unwrap();
+    capabilities.sort();
+    assert_eq!(
+        capabilities,
+        [
+            "action-icons",
+            "actions",
+            "body",
+            "body-hyperlinks",
+            "body-markup",
+            "inline-reply",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ids_are_distinct_and_not_zero() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let mut ids = HashSet::new();
+    for summary in ["one", "two", "three"] {
+        ids.insert(notify(&proxy, 0, summary, &[], HashMap::new()).await);
+    }
+    assert!(!ids.contains(&0));
+    assert_eq!(ids.len(), 3);
+    assert_eq!(daemon.control().await.count().await.unwrap(), (3, 0));
+}
+
+#[tokio::test]
+async fn every_hint() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut invoked_stream = proxy.receive_action_invoked().await.unwrap();
+    let pixels = vec![0xffu8; 2 * 2 * 4];
+    let hints = || {
+        HashMap::from([
+            ("urgency", Value::U8(2)),
+            ("category", Value::from("email.arrived")),
+            ("desktop-entry", Value::from("org.example.Mail")),
+            (
+                "image-data",
+                Value::from((2i32, 2i32, 8i32, true, 8i32, 4i32, pixels.clone())),
+            ),
+            ("image-path", Value::from("/nonexistent/avatar.png")),
+            ("sound-file", Value::from("/nonexistent/ding.oga")),
+            ("sound-name", Value::from("message-new-email")),
+            ("suppress-sound", Value::Bool(true)),
+            ("resident", Value::Bool(true)),
+            ("transient", Value::Bool(true)),
+            ("action-icons", Value::Bool(true)),
+            ("x", Value::I32(10)),
+            ("y", Value::I32(20)),
+            ("sender-pid", Value::I64(4242)),
+            ("value", Value::I32(50)),
+            ("x-canonical-private-synchronous", Value::from("volume")),
+            ("x-kde-reply-placeholder-text", Value::from("Reply")),
+            ("x-unknown-hint", Value::new(Value::U32(7))),
+        ])
+    };
+    let id = notify(&proxy, 0, "all of them", &["default", "Open"], hints()).await;
+    assert_ne!(id, 0);
+
+    let entry = entry(&control, id).await;
+    assert_eq!(entry.summary, "all of them");
+    assert_eq!(entry.urgency, 2);
+    assert_eq!(entry.category, "email.arrived");
+
+    let decoded = control.hints(id).await.unwrap();
+    let hint = |key: &str| {
+        let value = decoded.get(key);
+        Value::clone(value.unwrap_or_else(|| panic!("{key} was not decoded")))
+    };
+    assert_eq!(hint("desktop-entry"), Value::from("org.example.Mail"));
+    assert_eq!(hint("image-size"), Value::from((2i32, 2i32)));
+    assert_eq!(hint("image-path"), Value::from("/nonexistent/avatar.png"));
+    assert_eq!(hint("suppress-sound"), Value::Bool(true));
+    assert_eq!(hint("action-icons"), Value::Bool(true));
+    assert_eq!(hint("x"), Value::I32(10));
+    assert_eq!(hint("y"), Value::I32(20));
+    assert_eq!(hint("sender-pid"), Value::I64(4242));
+    assert_eq!(hint("value"), Value::U8(50));
+    assert_eq!(hint("synchronous"), Value::from("volume"));
+    assert_eq!(hint("x-kde-reply-placeholder-text"), Value::from("Reply"));
+    // kept as sent, undecoded
+    assert_eq!(hint("sound-name"), Value::from("message-new-email"));
+    assert_eq!(hint("x-unknown-hint"), Value::new(Value::U32(7)));
+
+    // resident: still there after its action was invoked
+    control.invoke(id, "default").await.unwrap();
+    assert_eq!(
+        invoked(&mut invoked_stream).await,
+        (id, "default".to_owned())
+    );
+    assert_eq!(control.count().await.unwrap(), (1, 0));
+
+    // transient: never in the history
+    let history = daemon.history().await.list(0, 10).await.unwrap();
+    assert!(history.iter().all(|entry| entry.id != id));
+
+    // synchronous: the same tag replaces it
+    let again = notify(&proxy, 0, "all of them again", &[], hints()).await;
+    assert_eq!(again, id);
+    assert_eq!(control.count().await.unwrap(), (1, 0));
+}
+
+#[tokio::test]
+async fn wrongly_typed_hints_are_ignored() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let hints = HashMap::from([
+        ("urgency", Value::from("critical")),
+        ("category", Value::I32(3)),
+        // not `(iiibiiay)`
+        ("image-data", Value::from((1i32, 1i32))),
+        ("resident", Value::from("yes")),
+        ("value", Value::from("half")),
+    ]);
+    let id = notify(&proxy, 0, "wrong types", &[], hints).await;
+
+    let entry = entry(&control, id).await;
+    assert_eq!(entry.urgency, 1);
+    assert_eq!(entry.category, "");
+}
+
+#[tokio::test]
+async fn urgency_as_any_integer() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    for urgency in [Value::U8(0), Value::I32(0), Value::U32(0), Value::I64(0)] {
+        let id = notify(&proxy, 0, "low", &[], HashMap::from([("urgency", urgency)])).await;
+        assert_eq!(entry(&control, id).await.urgency, 0);
+    }
+}
+
+#[tokio::test]
+async fn transient_notifications_are_not_kept() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let kept = notify(&proxy, 0, "kept", &[], HashMap::new()).await;
+    let transient = HashMap::from([("transient", Value::Bool(true))]);
+    let forgotten = notify(&proxy, 0, "forgotten", &[], transient).await;
+
+    let ids = daemon
+        .history()
+        .await
+        .list(0, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect::<Vec<_>>();
+    assert!(ids.contains(&kept));
+    assert!(!ids.contains(&forgotten));
+}
+
+#[tokio::test]
+async fn synchronous_notifications_replace_each_other() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let tag = || HashMap::from([("x-canonical-private-synchronous", Value::from("volume"))]);
+    let first = notify(&proxy, 0, "Volume 10%", &[], tag()).await;
+    let second = notify(&proxy, 0, "Volume 20%", &[], tag()).await;
+    assert_eq!(first, second);
+    assert_eq!(daemon.control().await.count().await.unwrap(), (1, 0));
+}
+
+#[tokio::test]
+async fn replaces_id() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+
+    let id = notify(&proxy, 0, "Downloading", &[], HashMap::new()).await;
+    let replaced = notify(&proxy, id, "Downloaded", &[], HashMap::new()).await;
+    assert_eq!(replaced, id);
+    assert_eq!(entry(&control, id).await.summary, "Downloaded");
+    assert_eq!(control.count().await.unwrap(), (1, 0));
+    // replacing is not closing
+    let signal = tokio::time::timeout(Duration::from_millis(200), closed.next()).await;
+    assert!(signal.is_err(), "replacing closed the notification");
+
+    // the spec asks for a new notification if the old one is gone
+    let unknown = id + 1000;
+    let new = notify(&proxy, unknown, "Fresh", &[], HashMap::new()).await;
+    assert_ne!(new, 0);
+    assert_ne!(new, id);
+    assert_eq!(control.count().await.unwrap(), (2, 0));
+}
+
+#[tokio::test]
+async fn close_notification() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+
+    let id = notify(&proxy, 0, "Closing soon", &[], HashMap::new()).await;
+    proxy.close_notification(id).await.unwrap();
+    assert_eq!(self::closed(&mut closed).await, (id, 3));
+    assert_eq!(daemon.control().await.count().await.unwrap(), (0, 0));
+
+    // closing it again is not an error
+    proxy.close_notification(id).await.unwrap();
+}
+
+#[tokio::test]
+async fn expiry() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+
+    let forever = notify(&proxy, 0, "Forever", &[], HashMap::new()).await;
+    let id = proxy
+        .notify("tests", 0, "", "Brief", "", &[], HashMap::new(), 100)
+        .await
+        .unwrap();
+    assert_eq!(self::closed(&mut closed).await, (id, 1));
+
+    let live = daemon.control().await.notifications().await.unwrap();
+    assert_eq!(
+        live.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+        [forever]
+    );
+}
+
+#[tokio::test]
+async fn actions() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+    let mut invoked_stream = proxy.receive_action_invoked().await.unwrap();
+
+    let actions = ["default", "Open", "archive", "Archive"];
+    let id = notify(&proxy, 0, "Mail", &actions, HashMap::new()).await;
+    assert!(control.invoke(id, "reply").await.is_err());
+    control.invoke(id, "archive").await.unwrap();
+    assert_eq!(
+        invoked(&mut invoked_stream).await,
+        (id, "archive".to_owned())
+    );
+    assert_eq!(self::closed(&mut closed).await, (id, 2));
+    assert_eq!(control.count().await.unwrap(), (0, 0));
+
+    // resident notifications stay after an action
+    let resident = HashMap::from([("resident", Value::Bool(true))]);
+    let id = notify(&proxy, 0, "Player", &actions, resident).await;
+    control.invoke(id, "default").await.unwrap();
+    assert_eq!(
+        invoked(&mut invoked_stream).await,
+        (id, "default".to_owned())
+    );
+    assert_eq!(control.count().await.unwrap(), (1, 0));
+}
+
+#[tokio::test]
+async fn dismiss() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+
+    let id = notify(&proxy, 0, "Dismiss me", &[], HashMap::new()).await;
+    control.dismiss(id).await.unwrap();
+    assert_eq!(self::closed(&mut closed).await, (id, 2));
+    assert!(control.dismiss(id).await.is_err());
+}
+
+#[tokio::test]
+async fn refuses_to_take_over_without_replace() {
+    let daemon = Daemon::start().await;
+    let first = owner(&daemon.conn).await;
+    let err = spawn(options(&daemon.bus.address))
+        .join()
+        .unwrap()
+        .unwrap_err();
+    assert!(err.to_string().contains("--replace"), "{err}");
+    assert_eq!(owner(&daemon.conn).await, first);
+}
+
+#[tokio::test]
+async fn replace() {
+    let daemon = Daemon::start().await;
+    let proxy = daemon.notifications().await;
+    let id = notify(&proxy, 0, "Left behind", &[], HashMap::new()).await;
+    // once replaced, signals of the old daemon no longer come from the owner
+    // of the name
+    let first = owner(&daemon.conn).await;
+    let old = NotificationsProxy::builder(&daemon.conn)
+        .destination(first.clone())
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let mut closed = old.receive_notification_closed().await.unwrap();
+
+    let _second = spawn(Options {
+        replace: true,
+        ..options(&daemon.bus.address)
+    });
+    assert_eq!(self::closed(&mut closed).await, (id, 4));
+    daemon.thread.join().unwrap().unwrap();
+    assert_ne!(owner(&daemon.conn).await, first);
+    let count = ControlProxy::new(&daemon.conn).await.unwrap().count().await;
+    assert_eq!(count.unwrap(), (0, 0));
+}
+
+#[tokio::test]
+async fn replacing_can_be_refused() {
+    let bus = Bus::start();
+    // a daemon that does not allow replacement
+    let other = ConnectionBuilder::address(&*bus.address)
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    other
+        .request_name_with_flags(NAME, RequestNameFlags::DoNotQueue.into())
+        .await
+        .unwrap();
+    let replace = Options {
+        replace: true,
+        ..options(&bus.address)
+    };
+    let err = spawn(replace).join().unwrap().unwrap_err();
+    assert!(
+        err.to_string().contains("does not let it be replaced"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn suppressed_by_rule() {
+    let config = env::temp_dir().join(format!("uwu-notify-suppress-{}.toml", process::id()));
+    let rule = "[[rule]]\nmatch = { app-name = \"^muted$\" }\nsuppress = true\n";
+    fs::write(&config, rule).unwrap();
+    let daemon = Daemon::start_with(|options| options.config = Some(config.clone())).await;
+    let proxy = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut closed = proxy.receive_notification_closed().await.unwrap();
+
+    // critical notifications never expire on their own, hidden ones are
+    // closed right away, as undefined rather than expired
+    let critical = HashMap::from([("urgency", Value::U8(2))]);
+    let hidden = proxy
+        .notify("muted", 0, "", "Hidden", "", &[], critical, -1)
+        .await
+        .unwrap();
+    assert_eq!(self::closed(&mut closed).await, (hidden, 4));
+
+    let shown = notify(&proxy, 0, "Shown", &[], HashMap::new()).await;
+    let live = control.notifications().await.unwrap();
+    assert_eq!(
+        live.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+        [shown]
+    );
+    assert_eq!(control.count().await.unwrap(), (1, 0));
+    fs::remove_file(config).unwrap();
+}
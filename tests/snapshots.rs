@@ -0,0 +1,212 @@
+//! Snapshot tests of how notifications are laid out and drawn.
+//!
+//! Every test renders notifications without a compositor and compares the
+//! result with `tests/snapshots/<name>.png`. With `UPDATE_SNAPSHOTS=1` the
+//! snapshots are written instead. On a mismatch, or if the snapshot is
+//! missing, the new rendering is written next to it as `<name>.new.png`.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+use uwu_notify::config::Config;
+use uwu_notify::headless::{self, Input};
+
+/// How far a channel may be off, to allow for floating point differences
+/// between machines.
+const TOLERANCE: u8 = 2;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn hints<const N: usize>(
+    hints: [(&str, serde_json::Value); N],
+) -> HashMap<String, serde_json::Value> {
+    hints
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+fn snapshot(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(name)
+}
+
+fn decode(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+#[track_caller]
+fn check(name: &str, notifications: Vec<Input>) {
+    check_with(name, &Config::default(), notifications);
+}
+
+#[track_caller]
+fn check_with(name: &str, config: &Config, notifications: Vec<Input>) {
+    let canvas = headless::render(notifications, config).unwrap();
+    let path = snapshot(&format!("{name}.png"));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        canvas.write_png(File::create(&path).unwrap()).unwrap();
+        return;
+    }
+    let new = snapshot(&format!("{name}.new.png"));
+    if !path.exists() {
+        canvas.write_png(File::create(&new).unwrap()).unwrap();
+        panic!(
+            "there is no snapshot of {name}, see {} and run with UPDATE_SNAPSHOTS=1 to accept it",
+            new.display(),
+        );
+    }
+
+    let (width, height, expected) = decode(&path);
+    let actual = canvas.to_rgba();
+    let differing = expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .filter(|(expected, actual)| {
+            expected
+                .iter()
+                .zip(*actual)
+                .any(|(expected, actual)| expected.abs_diff(*actual) > TOLERANCE)
+        })
+        .count();
+    if (width, height) != (canvas.width, canvas.height) || differing > 0 {
+        canvas.write_png(File::create(&new).unwrap()).unwrap();
+        panic!(
+            "{name} is {}x{} with {differing} pixels differing from the {width}x{height} snapshot, see {}",
+            canvas.width,
+            canvas.height,
+            new.display(),
+        );
+    }
+}
+
+#[test]
+fn long_summary() {
+    check(
+        "long_summary",
+        vec![Input {
+            app_name: "Build".to_owned(),
+            summary: "The nightly build of every workspace member finished after a rather long time, with warnings".to_owned(),
+            body: "Compiling took 41 minutes. Supercalifragilisticexpialidociously-long-identifiers-do-not-fit-on-one-line.".to_owned(),
+            ..Default::default()
+        }],
+    );
+}
+
+#[test]
+fn rtl() {
+    // the built-in fonts have no Hebrew or Arabic glyphs, so use a subset of
+    // DejaVu Sans that has them. egui neither shapes nor reorders
+    // right-to-left text, so the letters come out isolated and left to right.
+    let mut config = Config::default();
+    config.font.path = Some(fixture("DejaVuSans-subset.ttf"));
+    check_with(
+        "rtl",
+        &config,
+        vec![Input {
+            app_name: "Messages".to_owned(),
+            summary: "שלום עולם".to_owned(),
+            body: "مرحبا بالعالم, mixed with English".to_owned(),
+            ..Default::default()
+        }],
+    );
+}
+
+#[test]
+fn emoji() {
+    check(
+        "emoji",
+        vec![Input {
+            app_name: "Chat 💬".to_owned(),
+            summary: "Party tonight 🎉🎂".to_owned(),
+            body: "Bring snacks 🍕🍔🍟 and good vibes ✨".to_owned(),
+            ..Default::default()
+        }],
+    );
+}
+
+#[test]
+fn image() {
+    check(
+        "image",
+        vec![Input {
+            app_name: "Mail".to_owned(),
+            summary: "Alice".to_owned(),
+            body: "Are we still on for <b>Friday</b>?".to_owned(),
+            hints: hints([(
+                "image-path",
+                json!(fixture("avatar.png").display().to_string()),
+            )]),
+            ..Default::default()
+        }],
+    );
+}
+
+#[test]
+fn actions() {
+    check(
+        "actions",
+        vec![
+            Input {
+                app_name: "Updates".to_owned(),
+                summary: "3 updates available".to_owned(),
+                actions: [
+                    "default",
+                    "Open",
+                    "install",
+                    "Install now",
+                    "later",
+                    "Remind me later",
+                ]
+                .map(str::to_owned)
+                .to_vec(),
+                ..Default::default()
+            },
+            Input {
+                app_name: "Chat".to_owned(),
+                summary: "Bob".to_owned(),
+                body: "Did you see the build?".to_owned(),
+                actions: ["inline-reply", "Reply", "mark-read", "Mark as read"]
+                    .map(str::to_owned)
+                    .to_vec(),
+                hints: hints([("x-kde-reply-placeholder-text", json!("Answer Bob"))]),
+                ..Default::default()
+            },
+        ],
+    );
+}
+
+#[test]
+fn urgency_and_progress() {
+    check(
+        "urgency_and_progress",
+        vec![
+            Input {
+                app_name: "Battery".to_owned(),
+                summary: "Battery critically low".to_owned(),
+                hints: hints([("urgency", json!(2)), ("value", json!(4))]),
+                ..Default::default()
+            },
+            Input {
+                app_name: "Music".to_owned(),
+                summary: "Now playing".to_owned(),
+                hints: hints([("urgency", json!(0))]),
+                ..Default::default()
+            },
+        ],
+    );
+}